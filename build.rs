@@ -8,6 +8,19 @@ mod cli;
 #[path = "src/page.rs"]
 pub mod page;
 
+// Only the parts of the library modules that the arguments refer to
+#[path = "src/encryption"]
+mod encryption {
+    mod work_factor;
+    pub use work_factor::{MAX_WORK_FACTOR, MIN_WORK_FACTOR};
+}
+
+#[path = "src/payload"]
+mod payload {
+    mod qr_encoding;
+    pub use qr_encoding::QrEncoding;
+}
+
 fn main() -> std::io::Result<()> {
     let out_dir =
         std::path::PathBuf::from(std::env::var_os("OUT_DIR").ok_or(std::io::ErrorKind::NotFound)?);
@@ -41,6 +54,8 @@ fn main() -> std::io::Result<()> {
     // Re-run if the cli or page files change
     println!("cargo:rerun-if-changed=src/cli.rs");
     println!("cargo:rerun-if-changed=src/page.rs");
+    println!("cargo:rerun-if-changed=src/encryption/work_factor.rs");
+    println!("cargo:rerun-if-changed=src/payload/qr_encoding.rs");
 
     Ok(())
 }
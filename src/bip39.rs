@@ -0,0 +1,249 @@
+//! Detection, checksum verification, and compaction of BIP39 mnemonics
+use std::io::{self, Write};
+
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+
+use crate::memory::SecretBytes;
+
+/// The BIP39 English wordlist (2048 words, sorted)
+const WORDLIST: &str = include_str!("assets/wordlists/bip39_english.txt");
+
+/// Marker at the start of the compact form, followed by the entropy. The NUL
+/// byte keeps it from clashing with text inputs.
+pub const MARKER: &[u8] = b"\x00PAB";
+
+/// Valid mnemonic lengths (128 to 256 bits of entropy)
+const WORD_COUNTS: [usize; 5] = [12, 15, 18, 21, 24];
+
+/// Bits per word
+const BITS_PER_WORD: usize = 11;
+
+/// Words from the embedded wordlist
+fn words() -> Vec<&'static str> {
+    WORDLIST.lines().collect()
+}
+
+/// Check if the plaintext is a BIP39 mnemonic. Returns the entropy of a valid
+/// mnemonic, `None` for anything else, or an error for a mnemonic with an
+/// unknown word or an invalid checksum.
+pub fn detect(plaintext: &[u8]) -> Result<Option<Zeroizing<Vec<u8>>>, io::Error> {
+    let Ok(text) = std::str::from_utf8(plaintext) else {
+        return Ok(None);
+    };
+
+    let text = Zeroizing::new(text.to_lowercase());
+    let tokens: Vec<&str> = text.split_whitespace().collect();
+    if !WORD_COUNTS.contains(&tokens.len())
+        || !tokens
+            .iter()
+            .all(|token| token.chars().all(|c| c.is_ascii_alphabetic()))
+    {
+        return Ok(None);
+    }
+
+    let wordlist = words();
+    let indices: Zeroizing<Vec<Option<usize>>> = Zeroizing::new(
+        tokens
+            .iter()
+            .map(|token| wordlist.binary_search(token).ok())
+            .collect(),
+    );
+
+    let unknown: Vec<String> = indices
+        .iter()
+        .enumerate()
+        .filter(|(_, index)| index.is_none())
+        .map(|(position, _)| (position + 1).to_string())
+        .collect();
+    match unknown.len() {
+        0 => {}
+        // Most likely typos in a mnemonic
+        1 | 2 => {
+            return Err(invalid_mnemonic(&format!(
+                "Word {} of the BIP39 mnemonic isn't in the wordlist",
+                unknown.join(" and ")
+            )))
+        }
+        _ => return Ok(None),
+    }
+
+    let indices: Zeroizing<Vec<usize>> =
+        Zeroizing::new(indices.iter().flatten().copied().collect());
+    to_entropy(&indices).map(Some)
+}
+
+/// Entropy from the word indices, after checking the checksum
+fn to_entropy(indices: &[usize]) -> Result<Zeroizing<Vec<u8>>, io::Error> {
+    let mut bits = Zeroizing::new(vec![0u8; (indices.len() * BITS_PER_WORD).div_ceil(8)]);
+    for (i, index) in indices.iter().enumerate() {
+        for bit in 0..BITS_PER_WORD {
+            if (index >> (BITS_PER_WORD - 1 - bit)) & 1 == 1 {
+                let position = i * BITS_PER_WORD + bit;
+                bits[position / 8] |= 0x80 >> (position % 8);
+            }
+        }
+    }
+
+    // 32 bits of entropy per checksum bit, and 3 words per checksum bit
+    let checksum_bits = indices.len() / 3;
+    let entropy_len = indices.len() * 4 / 3;
+    let entropy = Zeroizing::new(bits[..entropy_len].to_vec());
+
+    let expected = Sha256::digest(&entropy[..])[0] >> (8 - checksum_bits);
+    if bits[entropy_len] >> (8 - checksum_bits) != expected {
+        return Err(invalid_mnemonic(
+            "The checksum of the BIP39 mnemonic is invalid, check the words for typos",
+        ));
+    }
+
+    Ok(entropy)
+}
+
+/// Word indices for the entropy
+fn to_indices(entropy: &[u8]) -> Result<Zeroizing<Vec<usize>>, io::Error> {
+    if !WORD_COUNTS
+        .iter()
+        .any(|count| count * 4 / 3 == entropy.len())
+    {
+        return Err(invalid_mnemonic(&format!(
+            "Invalid BIP39 entropy length: {} bytes",
+            entropy.len()
+        )));
+    }
+
+    let mut bits = Zeroizing::new(entropy.to_vec());
+    bits.push(Sha256::digest(entropy)[0]);
+
+    let word_count = entropy.len() * 3 / 4;
+    let indices = (0..word_count)
+        .map(|i| {
+            (0..BITS_PER_WORD).fold(0, |index, bit| {
+                let position = i * BITS_PER_WORD + bit;
+                (index << 1) | usize::from((bits[position / 8] >> (7 - position % 8)) & 1)
+            })
+        })
+        .collect();
+
+    Ok(Zeroizing::new(indices))
+}
+
+/// Compact form of a mnemonic: the marker and the entropy
+pub fn compact(entropy: &[u8]) -> Result<SecretBytes, io::Error> {
+    let mut compacted = SecretBytes::with_capacity(MARKER.len() + entropy.len())?;
+    compacted.write_all(MARKER)?;
+    compacted.write_all(entropy)?;
+
+    Ok(compacted)
+}
+
+/// Check if the (decrypted) plaintext is the compact form of a mnemonic
+pub fn is_compact(plaintext: &[u8]) -> bool {
+    plaintext.starts_with(MARKER)
+}
+
+/// Turn the compact form back into the mnemonic, one line of words separated
+/// by spaces
+pub fn expand(plaintext: &[u8]) -> Result<SecretBytes, io::Error> {
+    if !is_compact(plaintext) {
+        return Err(invalid_mnemonic("Not a compact BIP39 mnemonic"));
+    }
+
+    let indices = to_indices(&plaintext[MARKER.len()..])?;
+    let wordlist = words();
+
+    let mut mnemonic = SecretBytes::with_capacity(indices.len() * 9)?;
+    for (i, index) in indices.iter().enumerate() {
+        if i > 0 {
+            mnemonic.write_all(b" ")?;
+        }
+        mnemonic.write_all(wordlist[*index].as_bytes())?;
+    }
+    mnemonic.write_all(b"\n")?;
+
+    Ok(mnemonic)
+}
+
+/// Error for a mnemonic with typos or a bad checksum
+fn invalid_mnemonic(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test vectors from the BIP39 specification
+    const VECTORS: [(&str, &str); 3] = [
+        (
+            "00000000000000000000000000000000",
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+        ),
+        (
+            "7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f",
+            "legal winner thank year wave sausage worth useful legal winner thank yellow",
+        ),
+        (
+            "8080808080808080808080808080808080808080808080808080808080808080",
+            "letter advice cage absurd amount doctor acoustic avoid letter advice cage absurd amount doctor acoustic avoid letter advice cage absurd amount doctor acoustic bless",
+        ),
+    ];
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    #[test]
+    fn test_wordlist() {
+        let words = words();
+        assert_eq!(words.len(), 2048);
+        assert!(words.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn test_vectors() -> Result<(), io::Error> {
+        for (entropy, mnemonic) in VECTORS {
+            let detected = detect(format!("  {}\n", mnemonic.to_uppercase()).as_bytes())?;
+            assert_eq!(hex(&detected.unwrap()), entropy);
+
+            let compacted = compact(&detect(mnemonic.as_bytes())?.unwrap())?;
+            assert!(is_compact(&compacted));
+            assert_eq!(compacted.len(), MARKER.len() + entropy.len() / 2);
+
+            assert_eq!(&expand(&compacted)?[..], format!("{mnemonic}\n").as_bytes());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_checksum() {
+        let mnemonic = "abandon ".repeat(12);
+        let result = detect(mnemonic.as_bytes());
+        assert!(result.unwrap_err().to_string().contains("checksum"));
+    }
+
+    #[test]
+    fn test_typo() {
+        let mnemonic = "abandon abandon abandon abandon abandon abandn abandon abandon abandon abandon abandon about";
+        let result = detect(mnemonic.as_bytes());
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Word 6 of the BIP39 mnemonic isn't in the wordlist"
+        );
+    }
+
+    #[test]
+    fn test_not_a_mnemonic() -> Result<(), io::Error> {
+        assert!(detect(b"Hello world")?.is_none());
+        assert!(
+            detect(b"one two three four five six seven eight nine ten eleven twelve")?.is_none()
+        );
+        assert!(detect(&[0xff; 12])?.is_none());
+
+        // Wrong number of words
+        assert!(detect("abandon ".repeat(11).as_bytes())?.is_none());
+
+        Ok(())
+    }
+}
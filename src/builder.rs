@@ -1,11 +1,14 @@
 //! PaperAge
-use std::io::{BufReader, Cursor};
+use std::cell::Cell;
+use std::io::{self, BufReader, Cursor};
 
+use age::secrecy::{ExposeSecret, SecretString};
 use printpdf::{
     Color, IndirectFontRef, Line, LineDashPattern, Mm, PdfDocument, PdfDocumentReference,
     PdfLayerIndex, PdfLayerReference, PdfPageIndex, Point, Pt, Rgb, Svg, SvgTransform,
 };
 
+use crate::fingerprint::Fingerprint;
 use crate::page::*;
 
 pub mod svg;
@@ -16,6 +19,35 @@ pub const VERSION: Option<&str> = option_env!("CARGO_PKG_VERSION");
 /// Font width / height = 3 / 5
 const FONT_RATIO: f32 = 3.0 / 5.0;
 
+/// Depth of the descenders below the baseline, relative to the font size
+const DESCENT: f32 = 0.25;
+
+/// Font size of the details above the footer
+const DETAILS_FONT_SIZE: f32 = 8.0;
+
+/// Line height of the details above the footer
+const DETAILS_LINE_HEIGHT: f32 = 10.0;
+
+/// Space between the two tiers of a sheet
+const TIER_GAP: Mm = Mm(10.0);
+
+/// Space above the QR code of a tier for its fingerprint
+const TIER_HEADER: Mm = Mm(6.0);
+
+/// Part of the page that a ciphertext is laid out in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Slot {
+    /// The only ciphertext on the page: the QR code in the top half, the text
+    /// in the bottom half
+    Full,
+
+    /// The first of two tiers, in the left half of the page
+    Left,
+
+    /// The second of two tiers, in the right half of the page
+    Right,
+}
+
 /// Container for all the data required to insert elements into the PDF
 pub struct Document {
     /// A reference to the printpdf PDF document
@@ -35,6 +67,17 @@ pub struct Document {
 
     /// Page size
     pub page_size: PageSize,
+
+    /// Number of detail lines on the current page
+    details_lines: Cell<usize>,
+
+    /// Bottom of the text in the column to the left of the QR code (the
+    /// fingerprint) on the current page, if any
+    left_column_bottom: Cell<Option<Mm>>,
+
+    /// Bottom of the text in the column to the right of the QR code (the side
+    /// note) on the current page, if any
+    right_column_bottom: Cell<Option<Mm>>,
 }
 
 impl Document {
@@ -64,9 +107,29 @@ impl Document {
             title_font,
             code_font,
             page_size,
+            details_lines: Cell::new(0),
+            left_column_bottom: Cell::new(None),
+            right_column_bottom: Cell::new(None),
         })
     }
 
+    /// Add a new page to the PDF and make it the current page
+    pub fn add_page(&mut self) {
+        debug!("Adding page");
+
+        let dimensions = self.page_size.dimensions();
+
+        let (page, layer) = self
+            .doc
+            .add_page(dimensions.width, dimensions.height, "Layer 1");
+
+        self.page = page;
+        self.layer = layer;
+        self.details_lines.set(0);
+        self.left_column_bottom.set(None);
+        self.right_column_bottom.set(None);
+    }
+
     /// Get the default layer from the PDF
     fn get_current_layer(&self) -> PdfLayerReference {
         self.doc.get_page(self.page).get_layer(self.layer)
@@ -100,77 +163,169 @@ impl Document {
         );
     }
 
-    /// Insert the given PEM ciphertext in the bottom half of the page
-    pub fn insert_pem_text(&self, pem: String) {
-        debug!("Inserting PEM encoded ciphertext");
+    /// Insert the given PEM ciphertext below the QR code of the slot. Long
+    /// ciphertexts are split into two columns if the slot is wide enough.
+    /// Fails if it doesn't fit above the details even at the smallest font
+    /// size.
+    pub fn insert_pem_text(
+        &self,
+        pem: String,
+        slot: Slot,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        debug!("Inserting PEM encoded ciphertext ({slot:?})");
+
+        // Font sizes and line heights, from the largest to the smallest
+        const TEXT_SIZES: [(f32, f32); 5] = [
+            (13.0, 15.0),
+            (10.0, 12.0),
+            (8.0, 9.0),
+            (7.0, 8.0),
+            (6.5, 7.0),
+        ];
+
+        // Space between the columns of text
+        const COLUMN_GAP: Mm = Mm(6.0);
 
         let current_layer = self.get_current_layer();
 
-        let mut font_size = 13.0;
-        let mut line_height = 15.0;
-
-        // Rudimentary text scaling to get the Ascii Armor text to fit
-        if pem.lines().count() > 42 {
-            font_size = 6.5;
-            line_height = 7.0;
-        } else if pem.lines().count() > 39 {
-            font_size = 7.0;
-            line_height = 8.0;
-        } else if pem.lines().count() > 27 {
-            font_size = 8.0;
-            line_height = 9.0;
-        } else if pem.lines().count() > 22 {
-            font_size = 10.0;
-            line_height = 12.0;
+        let lines: Vec<&str> = pem.lines().collect();
+        let line_len = lines.iter().map(|line| line.len()).max().unwrap_or(0);
+        let (left, width) = self.pem_bounds(slot);
+
+        // Rudimentary text scaling to get the Ascii Armor text to fit above the
+        // footer and any details, and within the width of the slot
+        let bottom = self.details_top();
+        let Some((columns, font_size, line_height)) = [1, 2]
+            .into_iter()
+            .flat_map(|columns| {
+                TEXT_SIZES
+                    .into_iter()
+                    .map(move |(font_size, line_height)| (columns, font_size, line_height))
+            })
+            .find(|(columns, font_size, line_height)| {
+                let rows = lines.len().div_ceil(*columns);
+                let column_width = (width - COLUMN_GAP * (*columns - 1) as f32) / *columns as f32;
+                let top = self.pem_top(slot) - Mm::from(Pt(*font_size));
+                top - Mm::from(Pt(*line_height)) * rows.saturating_sub(1) as f32 >= bottom
+                    && FONT_RATIO * font_size * line_len as f32 <= column_width.into_pt().0
+            })
+        else {
+            return Err(layout_error(
+                "The ciphertext text doesn't fit on the sheet, even at the smallest font size",
+            ));
+        };
+
+        let rows = lines.len().div_ceil(columns);
+        let column_width = (width - COLUMN_GAP * (columns - 1) as f32) / columns as f32;
+
+        for (i, column) in lines.chunks(rows).enumerate() {
+            current_layer.begin_text_section();
+
+            current_layer.set_text_cursor(
+                left + (column_width + COLUMN_GAP) * i as f32,
+                self.pem_top(slot) - Mm::from(Pt(font_size)),
+            );
+            current_layer.set_line_height(line_height);
+            current_layer.set_font(&self.code_font, font_size);
+
+            for line in column {
+                current_layer.write_text(*line, &self.code_font);
+                current_layer.add_line_break();
+            }
+
+            current_layer.end_text_section();
         }
 
-        current_layer.begin_text_section();
+        Ok(())
+    }
 
-        current_layer.set_text_cursor(
-            self.page_size.dimensions().margin,
-            (self.page_size.dimensions().height / 2.0)
-                - Mm::from(Pt(font_size))
-                - self.page_size.dimensions().margin,
-        );
-        current_layer.set_line_height(line_height);
-        current_layer.set_font(&self.code_font, font_size);
+    /// Insert the QR code of the ciphertext payload at the top of the slot
+    pub fn insert_qr_code(
+        &self,
+        data: impl AsRef<[u8]>,
+        slot: Slot,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        debug!("Inserting QR code ({slot:?})");
+
+        let (left, size) = self.slot_bounds(slot);
+
+        self.add_qr_code(data, size, left, self.qrcode_bottom(slot))
+    }
 
-        for line in pem.lines() {
-            current_layer.write_text(line, &self.code_font);
-            current_layer.add_line_break();
+    /// Insert a small QR code of the signature in the column to the left of the
+    /// ciphertext QR code, aligned with its bottom edge
+    pub fn insert_signature_qr_code(
+        &self,
+        data: impl AsRef<[u8]>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        debug!("Inserting signature QR code");
+
+        let dimensions = self.page_size.dimensions();
+        let size = self.page_size.qrcode_left_edge() - dimensions.margin - Mm(3.0);
+        let bottom = dimensions.height - dimensions.margin * 2.0 - self.page_size.qrcode_size();
+
+        if self
+            .left_column_bottom
+            .get()
+            .is_some_and(|column_bottom| bottom + size > column_bottom)
+        {
+            return Err(layout_error(
+                "The signature QR code doesn't fit below the fingerprint",
+            ));
         }
 
-        current_layer.end_text_section();
+        self.add_qr_code(data, size, dimensions.margin, bottom)
     }
 
-    /// Insert the QR code of the PEM encoded ciphertext in the top half of the page
-    pub fn insert_qr_code(&self, text: String) -> Result<(), Box<dyn std::error::Error>> {
-        debug!("Inserting QR code");
+    /// Insert a small QR code of a public key in the column to the right of the
+    /// ciphertext QR code, aligned with its bottom edge
+    pub fn insert_public_key_qr_code(
+        &self,
+        data: impl AsRef<[u8]>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        debug!("Inserting public key QR code");
+
+        let dimensions = self.page_size.dimensions();
+        let left = self.page_size.qrcode_left_edge() + self.page_size.qrcode_size() + Mm(3.0);
+        let size = dimensions.width - dimensions.margin - left;
+        let bottom = dimensions.height - dimensions.margin * 2.0 - self.page_size.qrcode_size();
+
+        if self
+            .right_column_bottom
+            .get()
+            .is_some_and(|column_bottom| bottom + size > column_bottom)
+        {
+            return Err(layout_error(
+                "The public key QR code doesn't fit below the side note, the side note is too long",
+            ));
+        }
+
+        self.add_qr_code(data, size, left, bottom)
+    }
 
-        let image = svg::qrcode(text)?;
+    /// Add a QR code of the given size with its bottom left corner at the given
+    /// position
+    fn add_qr_code(
+        &self,
+        data: impl AsRef<[u8]>,
+        size: Mm,
+        left: Mm,
+        bottom: Mm,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let image = svg::qrcode(data)?;
         let qrcode = Svg::parse(image.as_str())?;
 
         let current_layer = self.get_current_layer();
 
-        let desired_qr_size = self.page_size.qrcode_size();
-        let initial_qr_size = Mm::from(qrcode.height.into_pt(300.0));
-        let qr_scale = desired_qr_size / initial_qr_size;
-
-        let scale = qr_scale;
         let dpi = 300.0;
-        let code_width = qrcode.width.into_pt(dpi) * scale;
-        let code_height = qrcode.height.into_pt(dpi) * scale;
-
-        let translate_x = (self.page_size.dimensions().width.into_pt() - code_width) / 2.0;
-        let translate_y = self.page_size.dimensions().height.into_pt()
-            - code_height
-            - (self.page_size.dimensions().margin.into_pt() * 2.0);
+        let initial_qr_size = Mm::from(qrcode.height.into_pt(dpi));
+        let scale = size / initial_qr_size;
 
         qrcode.add_to_layer(
             &current_layer,
             SvgTransform {
-                translate_x: Some(translate_x),
-                translate_y: Some(translate_y),
+                translate_x: Some(left.into_pt()),
+                translate_y: Some(bottom.into_pt()),
                 rotate: None,
                 scale_x: Some(scale),
                 scale_y: Some(scale),
@@ -238,41 +393,32 @@ impl Document {
         current_layer.add_line(divider);
     }
 
-    /// Insert the notes field label and placeholder in the PDF
-    pub fn insert_notes_field(&self, label: String, skip_line: bool) {
-        debug!("Inserting notes/passphrase placeholder");
+    /// Insert the notes field label and placeholder below the QR code of the
+    /// slot
+    pub fn insert_notes_field(&self, label: String, skip_line: bool, slot: Slot) {
+        debug!("Inserting notes/passphrase placeholder ({slot:?})");
         const MAX_LABEL_LEN: usize = 32;
 
         let current_layer = self.get_current_layer();
 
-        let baseline =
-            self.page_size.dimensions().height / 2.0 + self.page_size.dimensions().margin;
+        let baseline = self.notes_baseline(slot);
+        let (left, width) = self.slot_bounds(slot);
 
         let label_len = label.len();
 
         let font_size = 13.0;
 
-        current_layer.use_text(
-            label,
-            font_size,
-            self.page_size.qrcode_left_edge(),
-            baseline,
-            &self.title_font,
-        );
+        current_layer.use_text(label, font_size, left, baseline, &self.title_font);
 
         // If the placeholder line would be ridiculously short, don't draw it
         if label_len <= MAX_LABEL_LEN && !skip_line {
             self.draw_line(
                 vec![
                     Point::new(
-                        self.page_size.qrcode_left_edge()
-                            + Mm::from(Pt(FONT_RATIO * font_size * label_len as f32)),
-                        baseline - Mm(1.0),
-                    ),
-                    Point::new(
-                        self.page_size.qrcode_left_edge() + self.page_size.qrcode_size(),
+                        left + Mm::from(Pt(FONT_RATIO * font_size * label_len as f32)),
                         baseline - Mm(1.0),
                     ),
+                    Point::new(left + width, baseline - Mm(1.0)),
                 ],
                 1.0,
                 LineDashPattern::default(),
@@ -280,6 +426,349 @@ impl Document {
         }
     }
 
+    /// Insert the label of a tier between its QR code and its notes field
+    pub fn insert_tier_label(&self, label: String, slot: Slot) {
+        debug!("Inserting tier label: {label} ({slot:?})");
+
+        let (left, _) = self.slot_bounds(slot);
+
+        self.get_current_layer().use_text(
+            label,
+            13.0,
+            left,
+            self.qrcode_bottom(slot) - Mm(8.0),
+            &self.title_font,
+        );
+    }
+
+    /// Insert the fingerprint of a tier's ciphertext above its QR code, just
+    /// below the title, so that the tiers can be told apart at a glance
+    pub fn insert_tier_fingerprint(&self, fingerprint: &Fingerprint, slot: Slot) {
+        debug!("Inserting tier fingerprint: {fingerprint} ({slot:?})");
+
+        let (left, _) = self.slot_bounds(slot);
+
+        self.get_current_layer().use_text(
+            format!("Fingerprint {}", fingerprint.hex_groups().join("-")),
+            9.0,
+            left,
+            self.qrcode_top(slot) + Mm(2.0),
+            &self.code_font,
+        );
+    }
+
+    /// Draw the dashed lines between the two tiers, and between their QR codes
+    /// and their text. Call it after inserting the details.
+    pub fn insert_tier_dividers(&self) {
+        debug!("Inserting tier dividers");
+
+        let dimensions = self.page_size.dimensions();
+        let dash_pattern = LineDashPattern {
+            dash_1: Some(5),
+            ..LineDashPattern::default()
+        };
+
+        let center = dimensions.width / 2.0;
+        self.draw_line(
+            vec![
+                Point::new(center, dimensions.height - dimensions.margin * 2.0),
+                Point::new(center, self.details_top()),
+            ],
+            1.0,
+            dash_pattern,
+        );
+
+        let divider = self.notes_baseline(Slot::Left) - Mm(6.0);
+        self.draw_line(
+            vec![
+                Point::new(dimensions.margin, divider),
+                Point::new(dimensions.width - dimensions.margin, divider),
+            ],
+            1.0,
+            dash_pattern,
+        );
+    }
+
+    /// Left edge and width of the QR code of the slot
+    fn slot_bounds(&self, slot: Slot) -> (Mm, Mm) {
+        let dimensions = self.page_size.dimensions();
+        let half = (dimensions.width - dimensions.margin * 2.0 - TIER_GAP) / 2.0;
+
+        match slot {
+            Slot::Full => (
+                self.page_size.qrcode_left_edge(),
+                self.page_size.qrcode_size(),
+            ),
+            Slot::Left => (dimensions.margin, half),
+            Slot::Right => (dimensions.width - dimensions.margin - half, half),
+        }
+    }
+
+    /// Top edge of the QR code of the slot. The tiers leave room for their
+    /// fingerprints above it.
+    fn qrcode_top(&self, slot: Slot) -> Mm {
+        let dimensions = self.page_size.dimensions();
+
+        match slot {
+            Slot::Full => dimensions.height - dimensions.margin * 2.0,
+            Slot::Left | Slot::Right => dimensions.height - dimensions.margin * 2.0 - TIER_HEADER,
+        }
+    }
+
+    /// Bottom edge of the QR code of the slot
+    fn qrcode_bottom(&self, slot: Slot) -> Mm {
+        let (_, size) = self.slot_bounds(slot);
+
+        self.qrcode_top(slot) - size
+    }
+
+    /// Baseline of the notes field of the slot
+    fn notes_baseline(&self, slot: Slot) -> Mm {
+        let dimensions = self.page_size.dimensions();
+
+        match slot {
+            Slot::Full => dimensions.height / 2.0 + dimensions.margin,
+            // Below the tier label
+            Slot::Left | Slot::Right => self.qrcode_bottom(slot) - Mm(17.0),
+        }
+    }
+
+    /// Left edge and width of the ciphertext text of the slot
+    fn pem_bounds(&self, slot: Slot) -> (Mm, Mm) {
+        let dimensions = self.page_size.dimensions();
+
+        match slot {
+            Slot::Full => (
+                dimensions.margin,
+                dimensions.width - dimensions.margin * 2.0,
+            ),
+            Slot::Left | Slot::Right => self.slot_bounds(slot),
+        }
+    }
+
+    /// Top of the ciphertext text of the slot
+    fn pem_top(&self, slot: Slot) -> Mm {
+        let dimensions = self.page_size.dimensions();
+
+        match slot {
+            Slot::Full => dimensions.height / 2.0 - dimensions.margin,
+            Slot::Left | Slot::Right => self.notes_baseline(slot) - Mm(12.0),
+        }
+    }
+
+    /// Insert the list of recipient fingerprints in place of the notes field
+    pub fn insert_recipients_field(&self, fingerprints: Vec<String>) {
+        debug!("Inserting recipients list");
+        const MAX_LINES: usize = 3;
+        const LABEL: &str = "Encrypted to:";
+
+        let current_layer = self.get_current_layer();
+
+        let baseline =
+            self.page_size.dimensions().height / 2.0 + self.page_size.dimensions().margin;
+
+        let label_font_size = 13.0;
+        let font_size = 9.0;
+        let line_height = Mm::from(Pt(font_size + 2.0));
+
+        current_layer.use_text(
+            LABEL,
+            label_font_size,
+            self.page_size.qrcode_left_edge(),
+            baseline,
+            &self.title_font,
+        );
+
+        let mut lines = fingerprints.clone();
+        if lines.len() > MAX_LINES {
+            lines.truncate(MAX_LINES - 1);
+            lines.push(format!("+{} more", fingerprints.len() - lines.len()));
+        }
+
+        let left = self.page_size.qrcode_left_edge()
+            + Mm::from(Pt(FONT_RATIO * label_font_size * (LABEL.len() + 1) as f32));
+
+        for (i, line) in lines.into_iter().enumerate() {
+            current_layer.use_text(
+                line,
+                font_size,
+                left,
+                baseline - line_height * i as f32,
+                &self.code_font,
+            );
+        }
+    }
+
+    /// Insert short lines of text in the column to the right of the QR code.
+    /// The first line is the heading, lines that don't fit are truncated.
+    pub fn insert_side_note(&self, lines: Vec<String>) {
+        debug!("Inserting side note");
+
+        let dimensions = self.page_size.dimensions();
+        let left = self.page_size.qrcode_left_edge() + self.page_size.qrcode_size() + Mm(3.0);
+
+        let bottom = self.insert_column(lines, left, dimensions.width - dimensions.margin - left);
+        self.right_column_bottom.set(Some(bottom));
+    }
+
+    /// Insert the ciphertext fingerprint in the column to the left of the QR
+    /// code, just below the title
+    pub fn insert_fingerprint(&self, fingerprint: &Fingerprint) {
+        debug!("Inserting fingerprint: {fingerprint}");
+
+        let dimensions = self.page_size.dimensions();
+
+        let mut lines = vec![String::from("Fingerprint")];
+        let groups = fingerprint.hex_groups();
+        lines.extend(groups.chunks(3).map(|chunk| chunk.join(" ")));
+        lines.push(String::new());
+        lines.extend(fingerprint.words().into_iter().map(String::from));
+
+        let bottom = self.insert_column(
+            lines,
+            dimensions.margin,
+            self.page_size.qrcode_left_edge() - dimensions.margin - Mm(3.0),
+        );
+        self.left_column_bottom.set(Some(bottom));
+    }
+
+    /// Insert lines of text in a column beside the QR code, starting from its
+    /// top edge. The first line is the heading, lines that don't fit are
+    /// truncated. Returns the bottom of the text.
+    fn insert_column(&self, lines: Vec<String>, left: Mm, width: Mm) -> Mm {
+        let current_layer = self.get_current_layer();
+
+        let dimensions = self.page_size.dimensions();
+        let heading_font_size = 11.0;
+        let font_size = 9.0;
+        let line_height = Mm::from(Pt(font_size + 3.0));
+
+        let max_chars = (width.into_pt().0 / (FONT_RATIO * font_size)) as usize;
+
+        let mut baseline =
+            dimensions.height - dimensions.margin * 2.0 - Mm::from(Pt(heading_font_size));
+
+        for (i, line) in lines.into_iter().enumerate() {
+            let (font, size) = if i == 0 {
+                (&self.title_font, heading_font_size)
+            } else {
+                (&self.code_font, font_size)
+            };
+
+            let line = if line.chars().count() > max_chars {
+                let truncated: String = line.chars().take(max_chars.saturating_sub(3)).collect();
+                format!("{truncated}...")
+            } else {
+                line
+            };
+
+            current_layer.use_text(line, size, left, baseline, font);
+            baseline -= line_height;
+        }
+
+        // Baseline of the last line, with room for the descenders
+        baseline + line_height - Mm::from(Pt(font_size * DESCENT))
+    }
+
+    /// Insert a generated passphrase on the page, so that it can be stored
+    /// separately from the sheet with the ciphertext
+    pub fn insert_passphrase_stub(&self, passphrase: &SecretString, entropy_bits: f64) {
+        debug!("Inserting passphrase stub");
+
+        let current_layer = self.get_current_layer();
+
+        let dimensions = self.page_size.dimensions();
+        let font_size = 13.0;
+        let line_height = Mm::from(Pt(font_size + 4.0));
+        let max_chars = ((dimensions.width - dimensions.margin * 2.0).into_pt().0
+            / (FONT_RATIO * font_size)) as usize;
+
+        // Wrap the passphrase at word boundaries
+        let mut lines: Vec<String> = vec![];
+        for word in passphrase.expose_secret().split(' ') {
+            match lines.last_mut() {
+                Some(line) if line.len() + word.len() < max_chars => {
+                    line.push(' ');
+                    line.push_str(word);
+                }
+                _ => lines.push(word.to_string()),
+            }
+        }
+
+        let mut baseline = dimensions.height - dimensions.margin * 2.0 - line_height * 2.0;
+
+        current_layer.use_text(
+            "Passphrase:",
+            font_size,
+            dimensions.margin,
+            baseline,
+            &self.title_font,
+        );
+
+        for line in lines {
+            baseline -= line_height;
+            current_layer.use_text(
+                line,
+                font_size,
+                dimensions.margin,
+                baseline,
+                &self.code_font,
+            );
+        }
+
+        baseline -= line_height * 2.0;
+
+        current_layer.use_text(
+            format!("Entropy: {entropy_bits:.1} bits"),
+            10.0,
+            dimensions.margin,
+            baseline,
+            &self.title_font,
+        );
+
+        current_layer.use_text(
+            "Store this page separately from the encrypted sheet",
+            10.0,
+            dimensions.margin,
+            baseline - line_height,
+            &self.title_font,
+        );
+    }
+
+    /// Insert lines of small print above the footer. Subsequent calls stack the
+    /// lines upwards.
+    pub fn insert_details(&self, lines: Vec<String>) {
+        debug!("Inserting details");
+
+        let current_layer = self.get_current_layer();
+
+        let line_height = Mm::from(Pt(DETAILS_LINE_HEIGHT));
+        let existing = self.details_lines.get();
+        let baseline = self.page_size.dimensions().margin + Mm::from(Pt(17.0));
+
+        for (i, line) in lines.iter().rev().enumerate() {
+            current_layer.use_text(
+                line,
+                DETAILS_FONT_SIZE,
+                self.page_size.dimensions().margin,
+                baseline + line_height * (existing + i) as f32,
+                &self.code_font,
+            );
+        }
+
+        self.details_lines.set(existing + lines.len());
+    }
+
+    /// Height of the details on the current page
+    fn details_height(&self) -> Mm {
+        Mm::from(Pt(DETAILS_LINE_HEIGHT)) * self.details_lines.get() as f32
+    }
+
+    /// Top of the footer and the details on the current page
+    fn details_top(&self) -> Mm {
+        self.page_size.dimensions().margin + Mm(10.0) + self.details_height()
+    }
+
     /// Add the footer at the bottom of the page
     pub fn insert_footer(&self) {
         debug!("Inserting footer");
@@ -296,6 +785,11 @@ impl Document {
     }
 }
 
+/// Error for content that doesn't fit on the page
+fn layout_error(reason: &str) -> Box<dyn std::error::Error> {
+    Box::new(io::Error::new(io::ErrorKind::InvalidInput, reason))
+}
+
 #[test]
 fn test_paper_dimensions_default() {
     let default = PageDimensions::default();
@@ -323,19 +817,191 @@ fn test_new_letter_document() {
     assert_eq!(doc.page_size.dimensions(), crate::page::LETTER_PAGE);
 }
 
+#[test]
+fn test_add_page() {
+    let mut document = Document::new(String::from("Pages"), PageSize::A4).unwrap();
+    let first_page = document.page;
+
+    document.add_page();
+    assert_ne!(document.page, first_page);
+}
+
+#[test]
+fn test_side_note() {
+    let document = Document::new(String::from("Side note"), PageSize::Letter).unwrap();
+    document.insert_side_note(vec![
+        String::from("Share 1 of 3"),
+        String::from("A very long custodian name that doesn't fit"),
+    ]);
+}
+
+#[test]
+fn test_fingerprint() {
+    let document = Document::new(String::from("Fingerprint"), PageSize::A4).unwrap();
+    document.insert_fingerprint(&Fingerprint::of_ciphertext("ciphertext"));
+}
+
+#[test]
+fn test_signature_qrcode() {
+    let document = Document::new(String::from("Signature"), PageSize::Letter).unwrap();
+    let result = document.insert_signature_qr_code("-----BEGIN SSH SIGNATURE-----");
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_public_key_qrcode() {
+    let document = Document::new(String::from("Public key"), PageSize::A4).unwrap();
+    let result = document.insert_public_key_qr_code(
+        "age1g3jnt5c79rs5nulvthlswd4x0psepldrthmsp6t6y665ff5l7e4q38r8sy",
+    );
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_details() {
+    let mut document = Document::new(String::from("Details"), PageSize::A4).unwrap();
+    document.insert_details(vec![String::from("One"), String::from("Two")]);
+    document.insert_details(vec![String::from("Three")]);
+    assert_eq!(document.details_lines.get(), 3);
+
+    document.add_page();
+    assert_eq!(document.details_lines.get(), 0);
+}
+
 #[test]
 fn test_qrcode() {
     let result = Document::new(String::from("QR code"), PageSize::A4);
     let document = result.unwrap();
-    let result = document.insert_qr_code(String::from("payload"));
+    let result = document.insert_qr_code(String::from("payload"), Slot::Full);
     assert!(result.is_ok());
 }
 
+#[test]
+fn test_tiers() {
+    let document = Document::new(String::from("Tiers"), PageSize::Letter).unwrap();
+    let pem = include_str!("../tests/data/passphrase.age");
+
+    for (slot, label) in [(Slot::Left, "Everyday"), (Slot::Right, "Break-glass")] {
+        document.insert_tier_fingerprint(&Fingerprint::of_ciphertext(pem), slot);
+        assert!(document.insert_qr_code(pem, slot).is_ok());
+        document.insert_tier_label(String::from(label), slot);
+        document.insert_notes_field(String::from("Passphrase:"), false, slot);
+        assert!(document.insert_pem_text(String::from(pem), slot).is_ok());
+    }
+    document.insert_tier_dividers();
+
+    // The tiers don't overlap
+    let (left, width) = document.slot_bounds(Slot::Left);
+    let (right, _) = document.slot_bounds(Slot::Right);
+    assert!(left + width + TIER_GAP <= right + Mm(0.01));
+    assert_eq!(
+        document.qrcode_bottom(Slot::Left),
+        document.qrcode_bottom(Slot::Right)
+    );
+
+    // The fingerprints fit between the title and the QR codes
+    let dimensions = document.page_size.dimensions();
+    let title_bottom = dimensions.height - dimensions.margin - Mm::from(Pt(14.0)) - Mm(1.5);
+    let fingerprint_top = document.qrcode_top(Slot::Left) + Mm(2.0) + Mm::from(Pt(9.0));
+    assert!(fingerprint_top <= title_bottom);
+}
+
+#[test]
+fn test_full_slot() {
+    let document = Document::new(String::from("Full"), PageSize::A4).unwrap();
+
+    // Same layout as before there were slots
+    assert_eq!(
+        document.slot_bounds(Slot::Full),
+        (Mm(50.0), PageSize::A4.qrcode_size())
+    );
+    assert_eq!(document.notes_baseline(Slot::Full), Mm(158.5));
+    assert_eq!(document.pem_top(Slot::Full), Mm(138.5));
+}
+
 #[test]
 fn test_qrcode_too_large() {
     let document = Document::new(String::from("QR code"), PageSize::A4).unwrap();
-    let result = document.insert_qr_code(String::from(include_str!("../tests/data/too_large.txt")));
+    let result = document.insert_qr_code(
+        String::from(include_str!("../tests/data/too_large.txt")),
+        Slot::Full,
+    );
 
     assert!(result.is_err());
     assert!(result.unwrap_err().is::<qrcode::types::QrError>());
 }
+
+#[test]
+fn test_pem_text_too_large() {
+    let pem = include_str!("../tests/data/passphrase.age");
+
+    for page_size in [PageSize::A4, PageSize::Letter] {
+        let document = Document::new(String::from("PEM"), page_size.clone()).unwrap();
+        document.insert_details(vec![String::from("Detail"); 5]);
+        assert!(document
+            .insert_pem_text(String::from(pem), Slot::Full)
+            .is_ok());
+
+        // Details up to the ciphertext text (e.g. an RSA SSH signature)
+        let mut document = Document::new(String::from("PEM"), page_size).unwrap();
+        document.add_page();
+        let lines = ((document.pem_top(Slot::Full) - document.details_top())
+            .into_pt()
+            .0
+            / DETAILS_LINE_HEIGHT) as usize;
+        document.insert_details(vec![String::from("Detail"); lines]);
+        let result = document.insert_pem_text(String::from(pem), Slot::Full);
+        assert!(result.is_err());
+    }
+}
+
+#[test]
+fn test_pem_text_columns() {
+    // About what a compact QR code encoding holds
+    let line = "A".repeat(64);
+    let mut pem = vec!["-----BEGIN AGE ENCRYPTED FILE-----"];
+    pem.extend(vec![line.as_str(); 52]);
+    pem.push("-----END AGE ENCRYPTED FILE-----");
+    let pem = pem.join("\n");
+
+    for page_size in [PageSize::A4, PageSize::Letter] {
+        let document = Document::new(String::from("Columns"), page_size.clone()).unwrap();
+        document.insert_details(vec![String::from("Detail"); 3]);
+        assert!(document.insert_pem_text(pem.clone(), Slot::Full).is_ok());
+
+        // The tiers are too narrow for two columns
+        let document = Document::new(String::from("Columns"), page_size).unwrap();
+        assert!(document.insert_pem_text(pem.clone(), Slot::Left).is_err());
+    }
+}
+
+#[test]
+fn test_columns() {
+    for page_size in [PageSize::A4, PageSize::Letter] {
+        // The fingerprint leaves room for the signature QR code
+        let document = Document::new(String::from("Columns"), page_size.clone()).unwrap();
+        document.insert_fingerprint(&Fingerprint::of_ciphertext("ciphertext"));
+        assert!(document.insert_signature_qr_code("signature").is_ok());
+
+        // Two factors and a public key
+        let mut lines = vec![String::from("Two factors"); 4];
+        lines.push(String::new());
+        lines.extend(vec![String::from("age1g3jnt5c79rs5"); 5]);
+        let document = Document::new(String::from("Columns"), page_size.clone()).unwrap();
+        document.insert_side_note(lines.clone());
+        assert!(document
+            .insert_public_key_qr_code(
+                "age1g3jnt5c79rs5nulvthlswd4x0psepldrthmsp6t6y665ff5l7e4q38r8sy"
+            )
+            .is_ok());
+
+        // Side notes that run into the public key QR code
+        lines.extend(vec![String::from("Too long"); 10]);
+        let document = Document::new(String::from("Columns"), page_size).unwrap();
+        document.insert_side_note(lines);
+        let result = document.insert_public_key_qr_code(
+            "age1g3jnt5c79rs5nulvthlswd4x0psepldrthmsp6t6y665ff5l7e4q38r8sy",
+        );
+        assert!(result.is_err());
+    }
+}
@@ -1,9 +1,10 @@
 //! Generate SVG formnat QR codes
 use qrcode::{render::svg, types::QrError, EcLevel, QrCode};
 
-/// Generate a QR code svg for the given string. The error correction level of
-/// the QR code is optimised (less data → more error correction)
-pub fn qrcode(text: String) -> Result<String, QrError> {
+/// Generate a QR code svg for the given data. The error correction level of
+/// the QR code is optimised (less data → more error correction), and the
+/// encoding mode is picked based on the data (e.g. alphanumeric for base45).
+pub fn qrcode(data: impl AsRef<[u8]>) -> Result<String, QrError> {
     // QR Code Error Correction Capability (approx.)
     //     H: 30%
     //     Q: 25%
@@ -15,7 +16,7 @@ pub fn qrcode(text: String) -> Result<String, QrError> {
     let mut result: Result<QrCode, QrError> = Result::Err(QrError::DataTooLong);
     for ec_level in levels.iter() {
         debug!("Trying EC level {:?}", *ec_level);
-        result = QrCode::with_error_correction_level(text.clone(), *ec_level);
+        result = QrCode::with_error_correction_level(data.as_ref(), *ec_level);
 
         if result.is_ok() {
             break;
@@ -1,15 +1,112 @@
 //! Command line arguments
 use std::path::PathBuf;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use clap_verbosity_flag::Verbosity;
 
+use crate::encryption::{MAX_WORK_FACTOR, MIN_WORK_FACTOR};
 use crate::page::PageSize;
+use crate::payload::QrEncoding;
 
 /// Command line arguments
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Sheet options, when no subcommand is given
+    #[command(flatten)]
+    pub create: CreateArgs,
+
+    /// Print out the license for the embedded fonts
+    #[arg(long, default_value_t = false, exclusive = true)]
+    pub fonts_license: bool,
+
+    /// Refuse to continue if secrets can't be locked in memory or core dumps
+    /// can't be disabled
+    #[arg(long, global = true)]
+    pub paranoid: bool,
+
+    /// Verbose output for debugging
+    #[clap(flatten)]
+    pub verbose: Verbosity,
+}
+
+/// Arguments for creating a sheet
+#[derive(clap::Args, Debug)]
+pub struct CreateArgs {
+    /// Options for the sheet
+    #[command(flatten)]
+    pub sheet: SheetArgs,
+
+    /// Compress the input with deflate before encrypting it, if that makes it
+    /// smaller
+    #[arg(short = 'z', long, default_value_t = false)]
+    pub compress: bool,
+
+    /// The input is an OpenPGP secret key: only keep the secret key material,
+    /// like paperkey. Restore it with paper-age restore-openpgp.
+    #[arg(long, default_value_t = false)]
+    pub openpgp: bool,
+
+    /// Store the entropy of a BIP39 mnemonic instead of the words (16 to 32
+    /// bytes). paper-age decrypt turns it back into the words.
+    #[arg(long, default_value_t = false)]
+    pub bip39_entropy: bool,
+
+    /// Encrypt a BIP39 mnemonic with an invalid checksum as it is, instead of
+    /// refusing it
+    #[arg(long, default_value_t = false, conflicts_with = "bip39_entropy")]
+    pub allow_invalid_mnemonic: bool,
+
+    /// Encrypt the input to this age file with a new identity instead, and
+    /// only print the identity on the sheet. For inputs of any size.
+    #[arg(
+        long,
+        value_name = "AGE_FILE",
+        conflicts_with_all = [
+            "recipients", "recipients_files", "ssh_keys", "openpgp", "bip39_entropy",
+            "allow_invalid_mnemonic"
+        ]
+    )]
+    pub key_sheet: Option<PathBuf>,
+
+    /// Add a break-glass tier next to the input on the same sheet: this file,
+    /// encrypted with a separate passphrase. The input is the everyday tier.
+    #[arg(
+        long,
+        value_name = "INPUT",
+        conflicts_with_all = [
+            "recipients", "recipients_files", "ssh_keys", "second_factors",
+            "second_factors_files", "generate_passphrase", "openpgp", "key_sheet",
+            "shares", "holders", "sign_with"
+        ]
+    )]
+    pub break_glass: Option<PathBuf>,
+
+    /// Read the passphrase of the break-glass tier from the given file instead
+    /// of PAPERAGE_BREAK_GLASS_PASSPHRASE or a prompt
+    #[arg(long, value_name = "PATH", requires = "break_glass")]
+    pub break_glass_passphrase_file: Option<PathBuf>,
+
+    /// Label of the everyday tier, with --break-glass
+    #[arg(long, value_name = "LABEL", default_value = "Everyday")]
+    pub everyday_label: String,
+
+    /// Label of the break-glass tier
+    #[arg(long, value_name = "LABEL", default_value = "Break-glass")]
+    pub break_glass_label: String,
+
+    /// The path to the file to read. Defaults to standard input. Max. ~1.9KB.
+    pub input: Option<PathBuf>,
+}
+
+/// Options for laying out and encrypting sheets, shared by creating a sheet,
+/// rewrap, and keygen
+#[derive(clap::Args, Debug)]
+pub struct SheetArgs {
     /// Page title (max. 64 characters)
     #[arg(short, long, default_value = "PaperAge")]
     pub title: String,
@@ -26,10 +123,148 @@ pub struct Args {
     #[arg(short, long, default_value = "out.pdf")]
     pub output: PathBuf,
 
+    /// Also write the ASCII armored ciphertexts to this file, one after the
+    /// other in the order of the sheets
+    #[arg(long, value_name = "PATH")]
+    pub armor_output: Option<PathBuf>,
+
     /// Paper size
     #[arg(short = 's', long, default_value_t = PageSize::A4)]
     pub page_size: PageSize,
 
+    /// How the ciphertext is stored in the QR code. The compact encodings fit
+    /// more data, the printed text is always ASCII armored.
+    #[arg(long, default_value_t = QrEncoding::Armor)]
+    pub qr_encoding: QrEncoding,
+
+    /// Encrypt to the given recipient instead of a passphrase (e.g. age1…,
+    /// age1name1…, or an OpenSSH public key). May be repeated.
+    #[arg(
+        short,
+        long = "recipient",
+        value_name = "RECIPIENT",
+        conflicts_with = "passphrase_source"
+    )]
+    pub recipients: Vec<String>,
+
+    /// Encrypt to the recipients listed in the given file instead of a
+    /// passphrase. Also accepts OpenSSH authorized_keys files. May be repeated.
+    #[arg(
+        short = 'R',
+        long = "recipients-file",
+        value_name = "PATH",
+        conflicts_with = "passphrase_source"
+    )]
+    pub recipients_files: Vec<PathBuf>,
+
+    /// Encrypt to all the SSH public keys (*.pub) in ~/.ssh
+    #[arg(long, default_value_t = false, conflicts_with = "passphrase_source")]
+    pub ssh_keys: bool,
+
+    /// Also encrypt to the given recipient, inside the passphrase encryption.
+    /// Both the passphrase and an identity for one of the recipients are
+    /// needed to decrypt the sheet. May be repeated.
+    #[arg(
+        long = "second-factor",
+        value_name = "RECIPIENT",
+        conflicts_with_all = ["recipients", "recipients_files", "ssh_keys"]
+    )]
+    pub second_factors: Vec<String>,
+
+    /// Like --second-factor, for the recipients listed in the given file. May
+    /// be repeated.
+    #[arg(
+        long = "second-factors-file",
+        value_name = "PATH",
+        conflicts_with_all = ["recipients", "recipients_files", "ssh_keys"]
+    )]
+    pub second_factors_files: Vec<PathBuf>,
+
+    /// Generate a diceware passphrase with the given number of words (default: 6)
+    #[arg(
+        long,
+        value_name = "WORDS",
+        num_args = 0..=1,
+        default_missing_value = "6",
+        value_parser = clap::value_parser!(u8).range(1..=32),
+        conflicts_with_all = ["recipients", "recipients_files", "ssh_keys", "passphrase_source"]
+    )]
+    pub generate_passphrase: Option<u8>,
+
+    /// Write the generated passphrase to a separate PDF instead of showing it
+    /// in the terminal, so that it can be stored apart from the sheet. Must
+    /// differ from --output.
+    #[arg(long, value_name = "PATH", requires = "generate_passphrase")]
+    pub passphrase_output: Option<PathBuf>,
+
+    /// Where to read the passphrase from
+    #[command(flatten)]
+    pub passphrase: PassphraseArgs,
+
+    /// scrypt work factor (log2 N) for the passphrase, from 10 to 30. Defaults
+    /// to 18.
+    #[arg(
+        long,
+        value_name = "LOG2_N",
+        value_parser = clap::value_parser!(u8).range(MIN_WORK_FACTOR as i64..=MAX_WORK_FACTOR as i64),
+        conflicts_with_all = ["recipients", "recipients_files", "ssh_keys"]
+    )]
+    pub work_factor: Option<u8>,
+
+    /// Pick the scrypt work factor so that decryption takes about this many
+    /// seconds on this machine
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        value_parser = clap::value_parser!(u64).range(1..),
+        conflicts_with_all = ["work_factor", "recipients", "recipients_files", "ssh_keys"]
+    )]
+    pub work_factor_target: Option<u64>,
+
+    /// Minimum passphrase strength score (0–4) as estimated by zxcvbn
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u8).range(0..=4))]
+    pub min_passphrase_score: u8,
+
+    /// Use the passphrase even if it's weak
+    #[arg(long, default_value_t = false)]
+    pub allow_weak_passphrase: bool,
+
+    /// Split the input into this many Shamir shares, one sheet each
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u8).range(2..),
+        requires = "threshold"
+    )]
+    pub shares: Option<u8>,
+
+    /// Number of shares needed to recover the input
+    #[arg(
+        long,
+        value_name = "K",
+        value_parser = clap::value_parser!(u8).range(2..),
+        requires = "shares"
+    )]
+    pub threshold: Option<u8>,
+
+    /// Make a sheet for each holder, encrypted with their own passphrase. Each
+    /// passphrase is asked for and confirmed in turn. May be repeated.
+    #[arg(
+        long = "holder",
+        value_name = "NAME",
+        conflicts_with_all = ["recipients", "recipients_files", "ssh_keys", "shares", "generate_passphrase"]
+    )]
+    pub holders: Vec<String>,
+
+    /// Name of the custodian of each share, in order. May be repeated.
+    #[arg(long = "custodian", value_name = "NAME", requires = "shares")]
+    pub custodians: Vec<String>,
+
+    /// Sign the ciphertext with the given OpenSSH private key. The signature
+    /// is compatible with ssh-keygen -Y verify (namespace paper-age).
+    #[arg(long, value_name = "PRIVATE_KEY")]
+    pub sign_with: Option<PathBuf>,
+
     /// Overwrite the output file if it already exists
     #[arg(short, long, default_value_t = false)]
     pub force: bool,
@@ -37,16 +272,168 @@ pub struct Args {
     /// Draw a grid pattern for debugging layout issues
     #[arg(short, long, default_value_t = false)]
     pub grid: bool,
+}
 
-    /// Print out the license for the embedded fonts
-    #[arg(long, default_value_t = false, exclusive = true)]
-    pub fonts_license: bool,
+/// Where to read the passphrase from, besides the PAPERAGE_PASSPHRASE
+/// environment variable
+#[derive(clap::Args, Debug)]
+#[group(id = "passphrase_source", multiple = false)]
+pub struct PassphraseArgs {
+    /// Read the passphrase from the given file instead of the environment or
+    /// a prompt. A trailing newline is removed.
+    #[arg(long = "passphrase-file", value_name = "PATH")]
+    pub file: Option<PathBuf>,
 
-    /// Verbose output for debugging
-    #[clap(flatten)]
-    pub verbose: Verbosity,
+    /// Read the passphrase from the given inherited file descriptor instead of
+    /// the environment or a prompt. A trailing newline is removed. STDOUT and
+    /// STDERR (1 and 2) are refused.
+    #[cfg(unix)]
+    #[arg(long = "passphrase-fd", value_name = "FD")]
+    pub fd: Option<i32>,
 
-    /// The path to the file to read. Defaults to standard input. Max. ~1.9KB.
+    /// Ask for the passphrase with the given pinentry program (e.g.
+    /// pinentry-gnome3). By default, a pinentry or SSH_ASKPASS is only used
+    /// when STDIN isn't a terminal.
+    #[arg(long, value_name = "PROGRAM")]
+    pub pinentry: Option<PathBuf>,
+}
+
+/// Subcommands
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Decrypt a sheet from typed, pasted, or scanned text
+    Decrypt(DecryptArgs),
+
+    /// Recover a secret from decrypted Shamir shares
+    Combine(CombineArgs),
+
+    /// Re-encrypt an existing sheet with a new passphrase or recipients
+    Rewrap(Box<RewrapArgs>),
+
+    /// Generate an age identity and print it on an encrypted sheet, with the
+    /// public key in the clear
+    Keygen(Box<KeygenArgs>),
+
+    /// Rebuild an OpenPGP secret key from a decrypted --openpgp sheet and the
+    /// public key
+    RestoreOpenpgp(RestoreOpenpgpArgs),
+}
+
+/// Arguments for the decrypt subcommand
+#[derive(clap::Args, Debug)]
+pub struct DecryptArgs {
+    /// Output file name. Use - for STDOUT.
+    #[arg(short, long, default_value = "-")]
+    pub output: PathBuf,
+
+    /// Overwrite the output file if it already exists
+    #[arg(short, long, default_value_t = false)]
+    pub force: bool,
+
+    /// Decrypt with the identities in the given file (age identity file or
+    /// SSH private key). Sheets with a second factor need both the passphrase
+    /// and an identity. May be repeated.
+    #[arg(short, long = "identity", value_name = "PATH")]
+    pub identities: Vec<PathBuf>,
+
+    /// Verify the signature of the sheet with the given trusted OpenSSH public
+    /// key before decrypting it
+    #[arg(long, value_name = "PUBLIC_KEY")]
+    pub verify_with: Option<PathBuf>,
+
+    /// Read the signature from the given file instead of the input
+    #[arg(long, value_name = "PATH", requires = "verify_with")]
+    pub signature: Option<PathBuf>,
+
+    /// Where to read the passphrase from
+    #[command(flatten)]
+    pub passphrase: PassphraseArgs,
+
+    /// The ciphertext to decrypt: ASCII armored text (whitespace and
+    /// indentation are ignored), a scanned base45 QR code, or the age binary
+    /// format. Defaults to standard input.
+    pub input: Option<PathBuf>,
+}
+
+/// Arguments for the combine subcommand
+#[derive(clap::Args, Debug)]
+pub struct CombineArgs {
+    /// Output file name. Use - for STDOUT.
+    #[arg(short, long, default_value = "-")]
+    pub output: PathBuf,
+
+    /// Overwrite the output file if it already exists
+    #[arg(short, long, default_value_t = false)]
+    pub force: bool,
+
+    /// Decrypted share files (at least as many as the threshold)
+    #[arg(required = true, value_name = "SHARE")]
+    pub shares: Vec<PathBuf>,
+}
+
+/// Arguments for the rewrap subcommand
+#[derive(clap::Args, Debug)]
+#[command(mut_arg("title", |arg| arg.required(true).default_value(None::<&str>)))]
+pub struct RewrapArgs {
+    /// Decrypt the old sheet with the identities in the given file (age
+    /// identity file or SSH private key). May be repeated.
+    #[arg(long = "old-identity", value_name = "PATH")]
+    pub old_identities: Vec<PathBuf>,
+
+    /// Read the old passphrase from a file instead of the
+    /// PAPERAGE_OLD_PASSPHRASE environment variable or a prompt
+    #[arg(long, value_name = "PATH")]
+    pub old_passphrase_file: Option<PathBuf>,
+
+    /// Options for the new sheet. The title isn't stored in the ciphertext, so
+    /// it has to be given again.
+    #[command(flatten)]
+    pub sheet: SheetArgs,
+
+    /// Compress the input with deflate before encrypting it, if that makes it
+    /// smaller
+    #[arg(short = 'z', long, default_value_t = false)]
+    pub compress: bool,
+
+    /// Store the entropy of a BIP39 mnemonic instead of the words (16 to 32
+    /// bytes). paper-age decrypt turns it back into the words.
+    #[arg(long, default_value_t = false)]
+    pub bip39_entropy: bool,
+
+    /// Encrypt a BIP39 mnemonic with an invalid checksum as it is, instead of
+    /// refusing it
+    #[arg(long, default_value_t = false, conflicts_with = "bip39_entropy")]
+    pub allow_invalid_mnemonic: bool,
+
+    /// The text of the old sheet: ASCII armored text, a scanned base45 QR
+    /// code, or the age binary format. Defaults to standard input.
+    pub input: Option<PathBuf>,
+}
+
+/// Arguments for the keygen subcommand
+#[derive(clap::Args, Debug)]
+pub struct KeygenArgs {
+    /// Options for the sheet
+    #[command(flatten)]
+    pub sheet: SheetArgs,
+}
+
+/// Arguments for the restore-openpgp subcommand
+#[derive(clap::Args, Debug)]
+pub struct RestoreOpenpgpArgs {
+    /// The OpenPGP public key (ASCII armored or binary, e.g. from gpg --export)
+    #[arg(long, value_name = "PATH")]
+    pub public_key: PathBuf,
+
+    /// Output file name for the binary secret key. Use - for STDOUT.
+    #[arg(short, long, default_value = "-")]
+    pub output: PathBuf,
+
+    /// Overwrite the output file if it already exists
+    #[arg(short, long, default_value_t = false)]
+    pub force: bool,
+
+    /// The decrypted secret key material. Defaults to standard input.
     pub input: Option<PathBuf>,
 }
 
@@ -73,26 +460,458 @@ mod tests {
             "--skip-notes-line",
             "--output",
             "test.pdf",
+            "--min-passphrase-score",
+            "4",
+            "--allow-weak-passphrase",
+            "--generate-passphrase",
+            "--passphrase-output",
+            "passphrase.pdf",
+            "--work-factor",
+            "22",
+            "--compress",
             "input.txt",
         ]);
-        assert!(args.force);
-        assert!(args.grid);
-        assert_eq!(args.title, "Hello");
-        assert_eq!(args.notes_label, "Notes:");
-        assert!(args.skip_notes_line);
-        assert_eq!(args.output.to_str().unwrap(), "test.pdf");
-        assert_eq!(args.input.unwrap().to_str().unwrap(), "input.txt");
+        assert!(args.create.sheet.force);
+        assert!(args.create.sheet.grid);
+        assert_eq!(args.create.sheet.title, "Hello");
+        assert_eq!(args.create.sheet.notes_label, "Notes:");
+        assert!(args.create.sheet.skip_notes_line);
+        assert_eq!(args.create.sheet.output.to_str().unwrap(), "test.pdf");
+        assert_eq!(args.create.sheet.min_passphrase_score, 4);
+        assert!(args.create.sheet.allow_weak_passphrase);
+        assert_eq!(args.create.sheet.generate_passphrase, Some(6));
+        assert_eq!(
+            args.create.sheet.passphrase_output,
+            Some(PathBuf::from("passphrase.pdf"))
+        );
+        assert_eq!(args.create.sheet.work_factor, Some(22));
+        assert!(args.create.compress);
+        assert_eq!(args.create.input.unwrap().to_str().unwrap(), "input.txt");
     }
 
     #[test]
     fn test_defaults() {
         let args = Args::parse_from(["paper-age"]);
-        assert_eq!(args.title, "PaperAge");
-        assert_eq!(args.notes_label, "Passphrase:");
-        assert!(!args.skip_notes_line);
-        assert_eq!(args.output.to_str().unwrap(), "out.pdf");
-        assert_eq!(args.input, None);
-        assert!(!args.force);
+        assert_eq!(args.create.sheet.title, "PaperAge");
+        assert_eq!(args.create.sheet.notes_label, "Passphrase:");
+        assert!(!args.create.sheet.skip_notes_line);
+        assert_eq!(args.create.sheet.output.to_str().unwrap(), "out.pdf");
+        assert_eq!(args.create.input, None);
+        assert!(args.create.sheet.recipients.is_empty());
+        assert!(args.create.sheet.recipients_files.is_empty());
+        assert!(!args.create.sheet.ssh_keys);
+        assert_eq!(args.create.sheet.min_passphrase_score, 3);
+        assert!(!args.create.sheet.allow_weak_passphrase);
+        assert_eq!(args.create.sheet.generate_passphrase, None);
+        assert_eq!(args.create.sheet.passphrase_output, None);
+        assert_eq!(args.create.sheet.work_factor, None);
+        assert_eq!(args.create.sheet.work_factor_target, None);
+        assert_eq!(args.create.sheet.passphrase.file, None);
+        assert_eq!(args.create.sheet.passphrase.pinentry, None);
+        assert!(!args.create.compress);
+        assert!(args.command.is_none());
+        assert_eq!(args.create.sheet.shares, None);
+        assert_eq!(args.create.sheet.qr_encoding, QrEncoding::Armor);
+        assert!(!args.create.sheet.force);
+    }
+
+    #[test]
+    fn test_recipients() {
+        let args = Args::parse_from([
+            "paper-age",
+            "-r",
+            "age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p",
+            "--recipients-file",
+            "recipients.txt",
+            "--ssh-keys",
+        ]);
+        assert_eq!(
+            args.create.sheet.recipients,
+            vec!["age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p"]
+        );
+        assert_eq!(
+            args.create.sheet.recipients_files,
+            vec![PathBuf::from("recipients.txt")]
+        );
+        assert!(args.create.sheet.ssh_keys);
+    }
+
+    #[test]
+    fn test_shares() {
+        let args = Args::parse_from([
+            "paper-age",
+            "--shares",
+            "5",
+            "--threshold",
+            "3",
+            "--custodian",
+            "Alice",
+            "--custodian",
+            "Bob",
+        ]);
+        assert_eq!(args.create.sheet.shares, Some(5));
+        assert_eq!(args.create.sheet.threshold, Some(3));
+        assert_eq!(args.create.sheet.custodians, vec!["Alice", "Bob"]);
+
+        let result = Args::try_parse_from(["paper-age", "--shares", "5"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_combine() {
+        let args = Args::parse_from(["paper-age", "combine", "share1", "share2"]);
+        match args.command {
+            Some(Command::Combine(combine)) => {
+                assert_eq!(
+                    combine.shares,
+                    vec![PathBuf::from("share1"), PathBuf::from("share2")]
+                );
+                assert_eq!(combine.output, PathBuf::from("-"));
+            }
+            _ => panic!("Expected the combine subcommand"),
+        }
+
+        let result = Args::try_parse_from(["paper-age", "--title", "Hello", "combine", "share1"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_rewrap() {
+        let args = Args::parse_from([
+            "paper-age",
+            "rewrap",
+            "--old-passphrase-file",
+            "old.txt",
+            "--title",
+            "Rotated",
+            "-r",
+            "age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p",
+            "sheet.txt",
+        ]);
+        match args.command {
+            Some(Command::Rewrap(rewrap)) => {
+                assert_eq!(rewrap.old_passphrase_file, Some(PathBuf::from("old.txt")));
+                assert!(rewrap.old_identities.is_empty());
+                assert_eq!(rewrap.sheet.title, "Rotated");
+                assert_eq!(rewrap.sheet.recipients.len(), 1);
+                assert_eq!(rewrap.input, Some(PathBuf::from("sheet.txt")));
+            }
+            _ => panic!("Expected the rewrap subcommand"),
+        }
+
+        // The title isn't in the old sheet, so it has to be given again
+        let result = Args::try_parse_from(["paper-age", "rewrap", "sheet.txt"]);
+        assert!(result.is_err());
+
+        // Options for the input of a new sheet don't apply
+        for option in [
+            ["--key-sheet", "sheet.age"],
+            ["--break-glass", "other.txt"],
+            ["--everyday-label", "Daily"],
+        ] {
+            let result = Args::try_parse_from(
+                ["paper-age", "rewrap", "--title", "Rotated"]
+                    .into_iter()
+                    .chain(option)
+                    .chain(["sheet.txt"]),
+            );
+            assert!(result.is_err());
+        }
+        let result = Args::try_parse_from([
+            "paper-age",
+            "rewrap",
+            "--title",
+            "Rotated",
+            "--openpgp",
+            "sheet.txt",
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_keygen() {
+        let args = Args::parse_from(["paper-age", "keygen", "--title", "Backup key"]);
+        match args.command {
+            Some(Command::Keygen(keygen)) => {
+                assert_eq!(keygen.sheet.title, "Backup key");
+                assert_eq!(keygen.sheet.output, PathBuf::from("out.pdf"));
+            }
+            _ => panic!("Expected the keygen subcommand"),
+        }
+
+        // keygen has no input, and the plaintext is always the identity
+        for option in ["--compress", "--openpgp", "--bip39-entropy", "input.txt"] {
+            let result = Args::try_parse_from(["paper-age", "keygen", option]);
+            assert!(result.is_err());
+        }
+        for option in ["--key-sheet", "--break-glass"] {
+            let result = Args::try_parse_from(["paper-age", "keygen", option, "other.txt"]);
+            assert!(result.is_err());
+        }
+    }
+
+    #[test]
+    fn test_openpgp() {
+        let args = Args::parse_from(["paper-age", "--openpgp", "secret.asc"]);
+        assert!(args.create.openpgp);
+        assert!(!args.create.bip39_entropy);
+
+        let args = Args::parse_from(["paper-age", "--key-sheet", "dump.sql.age", "dump.sql"]);
+        assert_eq!(args.create.key_sheet, Some(PathBuf::from("dump.sql.age")));
+
+        let result = Args::try_parse_from([
+            "paper-age",
+            "--key-sheet",
+            "dump.sql.age",
+            "--recipient",
+            "age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p",
+        ]);
+        assert!(result.is_err());
+
+        let args = Args::parse_from([
+            "paper-age",
+            "restore-openpgp",
+            "--public-key",
+            "public.asc",
+            "secret.bin",
+        ]);
+        match args.command {
+            Some(Command::RestoreOpenpgp(restore)) => {
+                assert_eq!(restore.public_key, PathBuf::from("public.asc"));
+                assert_eq!(restore.input, Some(PathBuf::from("secret.bin")));
+                assert_eq!(restore.output, PathBuf::from("-"));
+            }
+            _ => panic!("Expected the restore-openpgp subcommand"),
+        }
+
+        let result = Args::try_parse_from(["paper-age", "restore-openpgp", "secret.bin"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_holders() {
+        let args = Args::parse_from(["paper-age", "--holder", "Alice", "--holder", "Bob"]);
+        assert_eq!(args.create.sheet.holders, vec!["Alice", "Bob"]);
+
+        let result = Args::try_parse_from([
+            "paper-age",
+            "--holder",
+            "Alice",
+            "--shares",
+            "3",
+            "--threshold",
+            "2",
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_break_glass() {
+        let args = Args::parse_from(["paper-age", "--break-glass", "master.txt", "everyday.txt"]);
+        assert_eq!(args.create.break_glass, Some(PathBuf::from("master.txt")));
+        assert_eq!(args.create.everyday_label, "Everyday");
+        assert_eq!(args.create.break_glass_label, "Break-glass");
+
+        let result = Args::try_parse_from([
+            "paper-age",
+            "--break-glass",
+            "master.txt",
+            "--recipient",
+            "age1g3jnt5c79rs5nulvthlswd4x0psepldrthmsp6t6y665ff5l7e4q38r8sy",
+        ]);
+        assert!(result.is_err());
+
+        let result = Args::try_parse_from([
+            "paper-age",
+            "--break-glass-passphrase-file",
+            "passphrase.txt",
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_decrypt() {
+        let args = Args::parse_from([
+            "paper-age",
+            "decrypt",
+            "-i",
+            "keys.txt",
+            "--output",
+            "secret.txt",
+            "sheet.txt",
+        ]);
+        match args.command {
+            Some(Command::Decrypt(decrypt)) => {
+                assert_eq!(decrypt.identities, vec![PathBuf::from("keys.txt")]);
+                assert_eq!(decrypt.output, PathBuf::from("secret.txt"));
+                assert_eq!(decrypt.input, Some(PathBuf::from("sheet.txt")));
+                assert!(!decrypt.force);
+            }
+            _ => panic!("Expected the decrypt subcommand"),
+        }
+
+        // Both are needed for sheets with a second factor
+        let args = Args::parse_from([
+            "paper-age",
+            "decrypt",
+            "-i",
+            "keys.txt",
+            "--passphrase-file",
+            "passphrase.txt",
+        ]);
+        match args.command {
+            Some(Command::Decrypt(decrypt)) => {
+                assert_eq!(decrypt.identities, vec![PathBuf::from("keys.txt")]);
+                assert_eq!(
+                    decrypt.passphrase.file,
+                    Some(PathBuf::from("passphrase.txt"))
+                );
+            }
+            _ => panic!("Expected the decrypt subcommand"),
+        }
+    }
+
+    #[test]
+    fn test_second_factor() {
+        let args = Args::parse_from([
+            "paper-age",
+            "--second-factor",
+            "age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p",
+            "--second-factors-file",
+            "recipients.txt",
+            "--generate-passphrase",
+        ]);
+        assert_eq!(args.create.sheet.second_factors.len(), 1);
+        assert_eq!(
+            args.create.sheet.second_factors_files,
+            vec![PathBuf::from("recipients.txt")]
+        );
+        assert_eq!(args.create.sheet.generate_passphrase, Some(6));
+
+        let result = Args::try_parse_from([
+            "paper-age",
+            "--second-factor",
+            "age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p",
+            "--ssh-keys",
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_signatures() {
+        let args = Args::parse_from(["paper-age", "--sign-with", "id_ed25519"]);
+        assert_eq!(
+            args.create.sheet.sign_with,
+            Some(PathBuf::from("id_ed25519"))
+        );
+
+        let args = Args::parse_from([
+            "paper-age",
+            "decrypt",
+            "--verify-with",
+            "id_ed25519.pub",
+            "--signature",
+            "signature.txt",
+        ]);
+        match args.command {
+            Some(Command::Decrypt(decrypt)) => {
+                assert_eq!(decrypt.verify_with, Some(PathBuf::from("id_ed25519.pub")));
+                assert_eq!(decrypt.signature, Some(PathBuf::from("signature.txt")));
+            }
+            _ => panic!("Expected the decrypt subcommand"),
+        }
+
+        let result = Args::try_parse_from(["paper-age", "decrypt", "--signature", "sig.txt"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_qr_encoding() {
+        let args = Args::parse_from(["paper-age", "--qr-encoding", "base45"]);
+        assert_eq!(args.create.sheet.qr_encoding, QrEncoding::Base45);
+
+        let args = Args::parse_from(["paper-age", "--qr-encoding", "binary"]);
+        assert_eq!(args.create.sheet.qr_encoding, QrEncoding::Binary);
+    }
+
+    #[test]
+    fn test_passphrase_sources() {
+        let args = Args::parse_from(["paper-age", "--passphrase-file", "passphrase.txt"]);
+        assert_eq!(
+            args.create.sheet.passphrase.file,
+            Some(PathBuf::from("passphrase.txt"))
+        );
+
+        #[cfg(unix)]
+        {
+            let args = Args::parse_from(["paper-age", "--passphrase-fd", "3"]);
+            assert_eq!(args.create.sheet.passphrase.fd, Some(3));
+
+            let result = Args::try_parse_from([
+                "paper-age",
+                "--passphrase-fd",
+                "3",
+                "--passphrase-file",
+                "passphrase.txt",
+            ]);
+            assert!(result.is_err());
+        }
+    }
+
+    #[test]
+    fn test_generate_passphrase_words() {
+        let args = Args::parse_from(["paper-age", "--generate-passphrase", "8"]);
+        assert_eq!(args.create.sheet.generate_passphrase, Some(8));
+    }
+
+    #[test]
+    fn test_generate_passphrase_conflicts_with_recipients() {
+        let result = Args::try_parse_from(["paper-age", "--generate-passphrase", "--ssh-keys"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_work_factor_range() {
+        for work_factor in ["1", "9", "31", "40"] {
+            let result = Args::try_parse_from(["paper-age", "--work-factor", work_factor]);
+            assert!(result.is_err(), "--work-factor {work_factor}");
+        }
+
+        let args = Args::parse_from(["paper-age", "--work-factor", "30"]);
+        assert_eq!(args.create.sheet.work_factor, Some(MAX_WORK_FACTOR));
+    }
+
+    #[test]
+    fn test_work_factor_target() {
+        let args = Args::parse_from(["paper-age", "--work-factor-target", "10"]);
+        assert_eq!(args.create.sheet.work_factor_target, Some(10));
+
+        let result = Args::try_parse_from([
+            "paper-age",
+            "--work-factor-target",
+            "10",
+            "--work-factor",
+            "20",
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_min_passphrase_score_range() {
+        let result = Args::try_parse_from(["paper-age", "--min-passphrase-score", "5"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_paranoid() {
+        let args = Args::parse_from(["paper-age", "--paranoid"]);
+        assert!(args.paranoid);
+
+        let args = Args::parse_from(["paper-age", "decrypt", "--paranoid", "sheet.txt"]);
+        assert!(args.paranoid);
+
+        let args = Args::parse_from(["paper-age"]);
+        assert!(!args.paranoid);
     }
 
     #[test]
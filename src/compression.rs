@@ -0,0 +1,107 @@
+//! Optional compression of the plaintext before encryption
+use std::io::{self, Read, Write};
+
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+
+use crate::memory::SecretBytes;
+
+/// Marker at the start of a compressed plaintext, followed by the raw deflate
+/// stream. The NUL byte keeps it from clashing with text inputs.
+pub const MARKER: &[u8] = b"\x00PAZ";
+
+/// Largest accepted decompressed plaintext, far more than fits on a sheet.
+/// Keeps a crafted payload from inflating into gigabytes of locked memory.
+pub const MAX_DECOMPRESSED_LEN: u64 = 4 * 1024 * 1024;
+
+/// Compress the plaintext with deflate. Returns `None` if compression
+/// wouldn't make the plaintext smaller.
+pub fn compress(plaintext: &[u8]) -> Result<Option<SecretBytes>, io::Error> {
+    let mut compressed = SecretBytes::with_capacity(MARKER.len() + plaintext.len())?;
+    compressed.write_all(MARKER)?;
+
+    let mut encoder = DeflateEncoder::new(compressed, Compression::best());
+    encoder.write_all(plaintext)?;
+    let compressed = encoder.finish()?;
+
+    if compressed.len() < plaintext.len() {
+        Ok(Some(compressed))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Check if the (decrypted) plaintext was compressed by PaperAge
+pub fn is_compressed(plaintext: &[u8]) -> bool {
+    plaintext.starts_with(MARKER)
+}
+
+/// Decompress a plaintext compressed with [`compress`]. Plaintexts without the
+/// marker are returned as-is.
+pub fn decompress(plaintext: &[u8]) -> Result<SecretBytes, io::Error> {
+    if !is_compressed(plaintext) {
+        return SecretBytes::from_vec(plaintext.to_vec());
+    }
+
+    let decoder = DeflateDecoder::new(&plaintext[MARKER.len()..]);
+    let decompressed = SecretBytes::read_from(&mut decoder.take(MAX_DECOMPRESSED_LEN + 1))?;
+    if decompressed.len() as u64 > MAX_DECOMPRESSED_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "The decompressed plaintext is larger than {} MiB",
+                MAX_DECOMPRESSED_LEN / 1024 / 1024
+            ),
+        ));
+    }
+
+    Ok(decompressed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() -> Result<(), io::Error> {
+        let plaintext = "correct horse battery staple\n".repeat(20);
+
+        let compressed = compress(plaintext.as_bytes())?.unwrap();
+        assert!(is_compressed(&compressed));
+        assert!(compressed.len() < plaintext.len());
+
+        assert_eq!(&decompress(&compressed)?[..], plaintext.as_bytes());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_incompressible() -> Result<(), io::Error> {
+        assert!(compress(b"abc")?.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_decompress_too_large() -> Result<(), io::Error> {
+        let mut encoder = DeflateEncoder::new(MARKER.to_vec(), Compression::best());
+        io::copy(
+            &mut io::repeat(0).take(MAX_DECOMPRESSED_LEN + 1),
+            &mut encoder,
+        )?;
+        let bomb = encoder.finish()?;
+
+        match decompress(&bomb) {
+            Err(e) => assert_eq!(e.kind(), io::ErrorKind::InvalidData),
+            Ok(_) => panic!("Decompressed more than the limit"),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_decompress_uncompressed() -> Result<(), io::Error> {
+        assert_eq!(&decompress(b"Hello")?[..], b"Hello");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,225 @@
+//! Age based decryption of typed, pasted, or scanned sheets
+use std::{
+    fs,
+    io::{self, BufReader},
+    path::Path,
+};
+
+use age::secrecy::SecretString;
+use base64::{
+    engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+    Engine,
+};
+
+use crate::{encryption::MAX_WORK_FACTOR, memory::SecretBytes, payload};
+
+/// Beginning of the age binary format
+const BINARY_HEADER: &[u8] = b"age-encryption.org/v1\n";
+
+/// Armor begin and end markers, without whitespace
+const BEGIN_MARKER: &str = "-----BEGINAGEENCRYPTEDFILE-----";
+const END_MARKER: &str = "-----ENDAGEENCRYPTEDFILE-----";
+
+/// Base64 that doesn't care about missing or extra padding, which is easy to
+/// get wrong when typing
+const LENIENT_BASE64: GeneralPurpose = GeneralPurpose::new(
+    &base64::alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// Convert the ciphertext from a sheet to the age binary format. Accepts ASCII
+/// armor (ignoring whitespace, indentation, CRLF line endings, and any text
+/// around it), base45 from a compact QR code, and the binary format.
+pub fn read_ciphertext(input: &[u8]) -> Result<Vec<u8>, io::Error> {
+    if is_age_binary(input) {
+        debug!("Reading the age binary format");
+        return Ok(input.to_vec());
+    }
+
+    let text = std::str::from_utf8(input).map_err(|_| not_ciphertext())?;
+
+    if remove_whitespace(text).contains(BEGIN_MARKER) {
+        debug!("Reading ASCII armor");
+        return dearmor_lenient(text);
+    }
+
+    let trimmed = text.trim_matches(['\r', '\n']);
+    match payload::base45_decode(trimmed) {
+        Ok(binary) if binary.starts_with(BINARY_HEADER) => {
+            debug!("Reading base45");
+            Ok(binary)
+        }
+        _ => Err(not_ciphertext()),
+    }
+}
+
+/// Decode ASCII armor that may have been mangled by typing or copy-pasting
+fn dearmor_lenient(text: &str) -> Result<Vec<u8>, io::Error> {
+    let compact = remove_whitespace(text);
+
+    let start = compact.find(BEGIN_MARKER).ok_or_else(not_ciphertext)? + BEGIN_MARKER.len();
+    let end = compact[start..]
+        .find(END_MARKER)
+        .map(|end| start + end)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "The end of the armor (-----END AGE ENCRYPTED FILE-----) is missing",
+            )
+        })?;
+
+    LENIENT_BASE64
+        .decode(&compact[start..end])
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid armor: {e}")))
+}
+
+/// Remove all whitespace, including line breaks
+fn remove_whitespace(text: &str) -> String {
+    text.chars().filter(|c| !c.is_whitespace()).collect()
+}
+
+/// Check if the data is in the age binary format, such as the inner layer of a
+/// two factor sheet
+pub fn is_age_binary(data: &[u8]) -> bool {
+    data.starts_with(BINARY_HEADER)
+}
+
+/// Check if the ciphertext was encrypted with a passphrase (instead of
+/// recipients)
+pub fn is_passphrase_encrypted(ciphertext: &[u8]) -> Result<bool, age::DecryptError> {
+    Ok(age::Decryptor::new(ciphertext)?.is_scrypt())
+}
+
+/// Decrypt the age binary ciphertext with a passphrase
+pub fn decrypt_with_passphrase(
+    ciphertext: &[u8],
+    passphrase: SecretString,
+) -> Result<SecretBytes, age::DecryptError> {
+    let mut identity = age::scrypt::Identity::new(passphrase);
+    identity.set_max_work_factor(MAX_WORK_FACTOR);
+
+    decrypt(ciphertext, &[Box::new(identity)])
+}
+
+/// Decrypt the age binary ciphertext with the given identities
+pub fn decrypt(
+    ciphertext: &[u8],
+    identities: &[Box<dyn age::Identity>],
+) -> Result<SecretBytes, age::DecryptError> {
+    let decryptor = age::Decryptor::new(ciphertext)?;
+    let mut reader = decryptor.decrypt(identities.iter().map(|i| i.as_ref()))?;
+
+    Ok(SecretBytes::read_from(&mut reader)?)
+}
+
+/// Read the identities from an age identity file or an SSH private key
+pub fn read_identities(path: &Path) -> Result<Vec<Box<dyn age::Identity>>, io::Error> {
+    debug!("Reading identities from {}", path.display());
+
+    let contents = fs::read(path)?;
+    let filename = path.to_string_lossy().to_string();
+
+    if let Ok(identity) =
+        age::ssh::Identity::from_buffer(BufReader::new(&contents[..]), Some(filename.clone()))
+    {
+        return match identity {
+            age::ssh::Identity::Unsupported(key) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("Unsupported SSH key {filename}: {key:?}"),
+            )),
+            identity => Ok(vec![Box::new(
+                identity.with_callbacks(crate::recipients::PluginCallbacks),
+            )]),
+        };
+    }
+
+    age::IdentityFile::from_buffer(BufReader::new(&contents[..]))?
+        .with_callbacks(crate::recipients::PluginCallbacks)
+        .into_identities()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{filename}: {e}")))
+}
+
+/// Error for an input that doesn't look like an age ciphertext
+fn not_ciphertext() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "The input doesn't look like an age ciphertext (ASCII armor, base45, or the binary format)",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encryption;
+
+    const PASSPHRASE: &str = "snakeoil";
+
+    fn encrypted() -> String {
+        let mut input = b"some secrets" as &[u8];
+        let passphrase = SecretString::from(String::from(PASSPHRASE));
+        let (_, armored) = encryption::encrypt_plaintext(&mut input, passphrase, 10).unwrap();
+
+        armored
+    }
+
+    #[test]
+    fn test_decrypt_armor() -> Result<(), Box<dyn std::error::Error>> {
+        let ciphertext = read_ciphertext(encrypted().as_bytes())?;
+        assert!(is_passphrase_encrypted(&ciphertext)?);
+
+        let plaintext =
+            decrypt_with_passphrase(&ciphertext, SecretString::from(String::from(PASSPHRASE)))?;
+        assert_eq!(&plaintext[..], b"some secrets");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_decrypt_mangled_armor() -> Result<(), Box<dyn std::error::Error>> {
+        let mangled = encrypted()
+            .lines()
+            .map(|line| format!("    {line}  \r\n"))
+            .collect::<Vec<String>>()
+            .join("\r\n");
+        let mangled = format!("Here's the sheet:\n\n{mangled}\nThanks!");
+
+        let ciphertext = read_ciphertext(mangled.as_bytes())?;
+        let plaintext =
+            decrypt_with_passphrase(&ciphertext, SecretString::from(String::from(PASSPHRASE)))?;
+        assert_eq!(&plaintext[..], b"some secrets");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_base45() -> Result<(), Box<dyn std::error::Error>> {
+        let armored = encrypted();
+        let binary = payload::dearmor(&armored)?;
+        let base45 = payload::base45_encode(&binary);
+
+        assert_eq!(read_ciphertext(format!("{base45}\n").as_bytes())?, binary);
+        assert_eq!(read_ciphertext(&binary)?, binary);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_wrong_passphrase() -> Result<(), Box<dyn std::error::Error>> {
+        let ciphertext = read_ciphertext(encrypted().as_bytes())?;
+        let result =
+            decrypt_with_passphrase(&ciphertext, SecretString::from(String::from("wrong")));
+
+        assert!(matches!(result, Err(age::DecryptError::DecryptionFailed)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_not_ciphertext() {
+        let result = read_ciphertext(b"Hello");
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        let result = read_ciphertext(b"-----BEGIN AGE ENCRYPTED FILE-----\nYWdl");
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,64 @@
+//! Diceware passphrase generation
+use age::secrecy::SecretString;
+use rand::{rngs::OsRng, Rng};
+
+/// The EFF large wordlist (7776 words)
+const WORDLIST: &str = include_str!("assets/wordlists/eff_large_wordlist.txt");
+
+/// Separator between the words of a generated passphrase
+pub const SEPARATOR: &str = " ";
+
+/// Words from the embedded wordlist
+pub fn words() -> Vec<&'static str> {
+    WORDLIST
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .collect()
+}
+
+/// Generate a passphrase of the given number of words using the OS random
+/// number generator
+pub fn generate(word_count: usize) -> SecretString {
+    let words = words();
+
+    let passphrase = (0..word_count)
+        .map(|_| words[OsRng.gen_range(0..words.len())])
+        .collect::<Vec<&str>>()
+        .join(SEPARATOR);
+
+    SecretString::from(passphrase)
+}
+
+/// Entropy of a generated passphrase in bits
+pub fn entropy_bits(word_count: usize) -> f64 {
+    word_count as f64 * (words().len() as f64).log2()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use age::secrecy::ExposeSecret;
+
+    #[test]
+    fn test_wordlist() {
+        let words = words();
+        assert_eq!(words.len(), 7776);
+        assert_eq!(words.first(), Some(&"abacus"));
+        assert_eq!(words.last(), Some(&"zoom"));
+    }
+
+    #[test]
+    fn test_generate() {
+        let passphrase = generate(6);
+        let words = words();
+
+        let generated: Vec<&str> = passphrase.expose_secret().split(SEPARATOR).collect();
+        assert_eq!(generated.len(), 6);
+        assert!(generated.iter().all(|word| words.contains(word)));
+    }
+
+    #[test]
+    fn test_entropy_bits() {
+        assert_eq!(entropy_bits(6).floor(), 77.0);
+    }
+}
@@ -1,21 +1,289 @@
 //! Age based encryption
-use std::io::Write;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 use age::armor::ArmoredWriter;
 use age::armor::Format::AsciiArmor;
-use age::secrecy::Secret;
+use age::secrecy::SecretString;
+use sha2::{Digest, Sha256};
 
-/// Encrypt the data from the reader and PEM encode the ciphertext
+use crate::memory;
+use crate::recipients::Recipient;
+
+mod work_factor;
+
+pub use work_factor::{MAX_WORK_FACTOR, MIN_WORK_FACTOR};
+
+/// Highest work factor that the Go age CLI accepts by default
+pub const AGE_DEFAULT_MAX_WORK_FACTOR: u8 = 22;
+
+/// Label used for the scrypt salt, as in the age specification
+const SCRYPT_SALT_LABEL: &[u8] = b"age-encryption.org/v1/scrypt";
+
+/// Encrypt the data from the reader with the passphrase, using the given scrypt
+/// work factor (log2 N), and PEM encode the ciphertext
 pub fn encrypt_plaintext(
     reader: &mut dyn std::io::BufRead,
-    passphrase: Secret<String>,
+    passphrase: SecretString,
+    work_factor: u8,
+) -> Result<(usize, String), Box<dyn std::error::Error>> {
+    debug!("Encrypting plaintext (work factor: {work_factor})");
+
+    let mut recipient = age::scrypt::Recipient::new(passphrase);
+    recipient.set_work_factor(work_factor);
+
+    let encryptor = age::Encryptor::with_recipients(iter::once(&recipient as _))?;
+
+    encrypt(reader, encryptor)
+}
+
+/// Work factor (log2 N) when neither a work factor nor a target duration is
+/// given, the same on every machine
+pub const DEFAULT_WORK_FACTOR: u8 = 18;
+
+/// Pick an scrypt work factor (log2 N) that takes about the target duration on
+/// this machine, within the accepted range. Also returns the estimated
+/// duration of the picked work factor.
+pub fn calibrate_work_factor(target: Duration) -> (u8, Duration) {
+    let (mut log_n, mut duration) = measure_work_factor();
+
+    // Time scales linearly with N
+    while duration < target && log_n < MAX_WORK_FACTOR {
+        log_n += 1;
+        duration = duration.saturating_mul(2);
+    }
+
+    (log_n, duration)
+}
+
+/// Estimate how long the given scrypt work factor (log2 N) takes on this
+/// machine
+pub fn estimate_duration(work_factor: u8) -> Duration {
+    let (log_n, duration) = measure_work_factor();
+
+    if work_factor >= log_n {
+        duration.saturating_mul(1 << (work_factor - log_n).min(31))
+    } else {
+        duration / (1 << (log_n - work_factor).min(31))
+    }
+}
+
+/// Time the smallest measurable scrypt work factor, starting from 2^10
+fn measure_work_factor() -> (u8, Duration) {
+    let measure = |log_n| {
+        let params = scrypt::Params::new(log_n, 8, 1, 32).expect("log_n < 64");
+        let mut output = [0; 32];
+
+        let start = Instant::now();
+        scrypt::scrypt(&[], SCRYPT_SALT_LABEL, &params, &mut output).expect("valid output length");
+        start.elapsed()
+    };
+
+    let mut log_n = MIN_WORK_FACTOR;
+    let mut duration = measure(log_n);
+
+    // Increase the work factor until there's something to measure
+    while duration.is_zero() && log_n < 20 {
+        log_n += 1;
+        duration = measure(log_n);
+    }
+
+    (log_n, duration)
+}
+
+/// Encrypt the data from the reader to the given recipients and PEM encode the
+/// ciphertext
+pub fn encrypt_to_recipients(
+    reader: &mut dyn std::io::BufRead,
+    recipients: &[Recipient],
 ) -> Result<(usize, String), Box<dyn std::error::Error>> {
-    debug!("Encrypting plaintext");
+    debug!("Encrypting plaintext to {} recipient(s)", recipients.len());
+
+    let encryptor =
+        age::Encryptor::with_recipients(recipients.iter().map(|r| r.recipient.as_ref() as _))?;
 
-    let mut plaintext: Vec<u8> = vec![];
-    reader.read_to_end(&mut plaintext)?;
+    encrypt(reader, encryptor)
+}
+
+/// Encrypt the data from the reader to the given recipients, and then encrypt
+/// the resulting age binary ciphertext with the passphrase and PEM encode it.
+/// Decrypting it needs both the passphrase and an identity for one of the
+/// recipients.
+pub fn encrypt_two_factor(
+    reader: &mut dyn std::io::BufRead,
+    recipients: &[Recipient],
+    passphrase: SecretString,
+    work_factor: u8,
+) -> Result<(usize, String), Box<dyn std::error::Error>> {
+    debug!(
+        "Encrypting plaintext to {} recipient(s) inside a passphrase layer",
+        recipients.len()
+    );
 
-    let encryptor = age::Encryptor::with_user_passphrase(passphrase);
+    let plaintext = memory::SecretBytes::read_from(reader)?;
+
+    let encryptor =
+        age::Encryptor::with_recipients(recipients.iter().map(|r| r.recipient.as_ref() as _))?;
+    let mut inner = vec![];
+    let mut writer = encryptor.wrap_output(&mut inner)?;
+    writer.write_all(&plaintext)?;
+    writer.finish()?;
+
+    let (_, armored) = encrypt_plaintext(&mut inner.as_slice(), passphrase, work_factor)?;
+
+    Ok((plaintext.len(), armored))
+}
+
+/// Size and hash of an age file written by [`encrypt_to_file`]. The file is
+/// written to a temporary file next to its path, and only moved into place by
+/// [`EncryptedFile::persist`]. Otherwise, it's removed when this is dropped.
+#[derive(Debug)]
+pub struct EncryptedFile {
+    /// Size in bytes
+    pub size: u64,
+
+    /// SHA-256 hash, as computed by sha256sum
+    pub sha256: [u8; 32],
+
+    /// Final path of the file
+    path: PathBuf,
+
+    /// Temporary file with the ciphertext, until it's persisted
+    temp_path: Option<PathBuf>,
+}
+
+impl EncryptedFile {
+    /// SHA-256 hash as hex
+    pub fn sha256_hex(&self) -> String {
+        self.sha256.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    /// Move the file into place, replacing any existing file at its path
+    pub fn persist(mut self) -> Result<(), io::Error> {
+        let Some(temp_path) = self.temp_path.take() else {
+            return Ok(());
+        };
+
+        debug!("Moving {} to {}", temp_path.display(), self.path.display());
+        if let Err(e) = fs::rename(&temp_path, &self.path) {
+            let _ = fs::remove_file(&temp_path);
+            return Err(e);
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for EncryptedFile {
+    fn drop(&mut self) {
+        if let Some(temp_path) = &self.temp_path {
+            debug!("Removing {}", temp_path.display());
+            let _ = fs::remove_file(temp_path);
+        }
+    }
+}
+
+/// Encrypt all the data from the reader to the recipient, in the age binary
+/// format, and write it to a temporary file in the same directory as the path.
+/// Used for inputs that are too large for a sheet, so the data is streamed
+/// instead of kept in a locked buffer.
+pub fn encrypt_to_file(
+    reader: &mut dyn std::io::BufRead,
+    recipient: &age::x25519::Recipient,
+    path: &Path,
+) -> Result<EncryptedFile, Box<dyn std::error::Error>> {
+    let temp_path = temp_path(path);
+    debug!("Encrypting the input to {}", temp_path.display());
+
+    let file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&temp_path)?;
+    let file = HashingWriter::new(BufWriter::new(file));
+
+    match stream_to(reader, recipient, file) {
+        Ok(file) => Ok(EncryptedFile {
+            size: file.size,
+            sha256: file.hasher.finalize().into(),
+            path: path.to_path_buf(),
+            temp_path: Some(temp_path),
+        }),
+        Err(e) => {
+            // Don't leave a truncated file behind
+            let _ = fs::remove_file(&temp_path);
+            Err(e)
+        }
+    }
+}
+
+/// Hidden temporary file next to the path, so that it can be renamed into
+/// place on the same file system
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| String::from("paper-age"));
+
+    path.with_file_name(format!(".{name}.{}.tmp", std::process::id()))
+}
+
+/// Encrypt all the data from the reader to the recipient and write it to the
+/// output. Returns the output.
+fn stream_to<W: Write>(
+    reader: &mut dyn std::io::BufRead,
+    recipient: &age::x25519::Recipient,
+    output: W,
+) -> Result<W, Box<dyn std::error::Error>> {
+    let encryptor = age::Encryptor::with_recipients(iter::once(recipient as _))?;
+    let mut writer = encryptor.wrap_output(output)?;
+    io::copy(reader, &mut writer)?;
+
+    let mut output = writer.finish()?;
+    output.flush()?;
+
+    Ok(output)
+}
+
+/// Writer that counts and hashes everything written to the inner writer
+struct HashingWriter<W: Write> {
+    inner: W,
+    hasher: Sha256,
+    size: u64,
+}
+
+impl<W: Write> HashingWriter<W> {
+    fn new(inner: W) -> HashingWriter<W> {
+        HashingWriter {
+            inner,
+            hasher: Sha256::new(),
+            size: 0,
+        }
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(data)?;
+        self.hasher.update(&data[..written]);
+        self.size += written as u64;
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Encrypt the data from the reader with the given encryptor
+fn encrypt(
+    reader: &mut dyn std::io::BufRead,
+    encryptor: age::Encryptor,
+) -> Result<(usize, String), Box<dyn std::error::Error>> {
+    let plaintext = memory::SecretBytes::read_from(reader)?;
 
     let mut encrypted = vec![];
 
@@ -39,8 +307,8 @@ mod tests {
     #[test]
     fn test_armored_output() {
         let mut input = b"some secrets" as &[u8];
-        let passphrase = Secret::new(String::from("snakeoil"));
-        let result = encrypt_plaintext(&mut input, passphrase);
+        let passphrase = SecretString::from(String::from("snakeoil"));
+        let result = encrypt_plaintext(&mut input, passphrase, 10);
 
         assert!(result.is_ok());
 
@@ -53,4 +321,128 @@ mod tests {
         let last_line: &str = armored.lines().last().unwrap();
         assert_eq!(last_line, "-----END AGE ENCRYPTED FILE-----")
     }
+
+    #[test]
+    fn test_work_factor() {
+        let mut input = b"some secrets" as &[u8];
+        let passphrase = SecretString::from(String::from("snakeoil"));
+        let (_, armored) = encrypt_plaintext(&mut input, passphrase, 12).unwrap();
+
+        let mut reader = age::armor::ArmoredReader::new(armored.as_bytes());
+        let mut header = vec![0; 128];
+        std::io::Read::read(&mut reader, &mut header).unwrap();
+
+        let header = String::from_utf8_lossy(&header);
+        let stanza = header.lines().nth(1).unwrap();
+        assert!(stanza.starts_with("-> scrypt "));
+        assert!(stanza.ends_with(" 12"));
+    }
+
+    #[test]
+    fn test_calibrate_work_factor() {
+        let (fast, _) = calibrate_work_factor(Duration::from_millis(1));
+        let (slow, estimate) = calibrate_work_factor(Duration::from_secs(10));
+
+        assert!(fast >= MIN_WORK_FACTOR);
+        assert!(slow > fast);
+        assert!(estimate >= Duration::from_secs(10));
+
+        // Never more than what paper-age can decrypt
+        assert_eq!(
+            calibrate_work_factor(Duration::from_secs(u64::MAX / 4)).0,
+            MAX_WORK_FACTOR
+        );
+    }
+
+    #[test]
+    fn test_encrypt_to_file() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = std::env::temp_dir().join(format!("paper-age-test-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        let path = dir.join("large.age");
+
+        let data = vec![0x42; 100_000];
+        let identity = age::x25519::Identity::generate();
+        let encrypted = encrypt_to_file(&mut data.as_slice(), &identity.to_public(), &path)?;
+
+        // Nothing is at the path until the file is persisted
+        assert!(!path.exists());
+        let size = encrypted.size;
+        let sha256 = encrypted.sha256;
+        assert_eq!(encrypted.sha256_hex().len(), 64);
+        encrypted.persist()?;
+
+        let ciphertext = fs::read(&path)?;
+        fs::remove_dir_all(&dir)?;
+
+        assert_eq!(size, ciphertext.len() as u64);
+        assert_eq!(&sha256[..], &Sha256::digest(&ciphertext)[..]);
+
+        let decrypted = crate::decryption::decrypt(&ciphertext, &[Box::new(identity)])?;
+        assert_eq!(&decrypted[..], &data[..]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_encrypt_to_file_dropped() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = std::env::temp_dir().join(format!("paper-age-drop-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        let path = dir.join("large.age");
+        fs::write(&path, "existing")?;
+
+        let identity = age::x25519::Identity::generate();
+        let encrypted = encrypt_to_file(&mut &b"data"[..], &identity.to_public(), &path)?;
+        drop(encrypted);
+
+        // The existing file is untouched and the temporary file is gone
+        assert_eq!(fs::read_to_string(&path)?, "existing");
+        assert_eq!(fs::read_dir(&dir)?.count(), 1);
+        fs::remove_dir_all(&dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_recipients_output() {
+        let mut input = b"some secrets" as &[u8];
+        let identity = age::x25519::Identity::generate();
+        let recipient = crate::recipients::parse_recipient(&identity.to_public().to_string());
+        let result = encrypt_to_recipients(&mut input, &[recipient.unwrap()]);
+
+        assert!(result.is_ok());
+
+        let (plaintext_size, armored) = result.unwrap();
+        assert_eq!(plaintext_size, 12);
+
+        let first_line: String = armored.lines().take(1).collect();
+        assert_eq!(first_line, "-----BEGIN AGE ENCRYPTED FILE-----");
+    }
+
+    #[test]
+    fn test_two_factor_output() -> Result<(), Box<dyn std::error::Error>> {
+        let mut input = b"some secrets" as &[u8];
+        let identity = age::x25519::Identity::generate();
+        let recipient = crate::recipients::parse_recipient(&identity.to_public().to_string())?;
+        let passphrase = SecretString::from(String::from("snakeoil"));
+        let (plaintext_size, armored) =
+            encrypt_two_factor(&mut input, &[recipient], passphrase.clone(), 10)?;
+        assert_eq!(plaintext_size, 12);
+
+        let binary = crate::payload::dearmor(&armored)?;
+        let inner = crate::decryption::decrypt_with_passphrase(&binary, passphrase)?;
+        assert!(crate::decryption::is_age_binary(&inner));
+
+        let plaintext = crate::decryption::decrypt(&inner, &[Box::new(identity)])?;
+        assert_eq!(&plaintext[..], b"some secrets");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_no_recipients() {
+        let mut input = b"some secrets" as &[u8];
+        let result = encrypt_to_recipients(&mut input, &[]);
+
+        assert!(result.is_err());
+    }
 }
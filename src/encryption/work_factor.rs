@@ -0,0 +1,8 @@
+//! Accepted scrypt work factors
+
+/// Lowest accepted scrypt work factor (log2 N)
+pub const MIN_WORK_FACTOR: u8 = 10;
+
+/// Highest scrypt work factor (log2 N) for both encryption and decryption.
+/// Higher work factors would need more than 128 GiB of memory.
+pub const MAX_WORK_FACTOR: u8 = 30;
@@ -0,0 +1,103 @@
+//! Short fingerprints of the ciphertext for telling sheets apart
+use std::fmt;
+
+use crate::diceware;
+
+/// Number of BLAKE3 output bytes in the fingerprint (80 bits)
+const LENGTH: usize = 10;
+
+/// Number of words in the word based form (about 52 bits)
+const WORDS: usize = 4;
+
+/// Truncated BLAKE3 hash of the ASCII armored ciphertext. It's never computed
+/// over the plaintext, so it doesn't reveal anything about it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fingerprint {
+    digest: [u8; LENGTH],
+}
+
+impl Fingerprint {
+    /// Fingerprint of the ASCII armored ciphertext, as printed on the sheet
+    pub fn of_ciphertext(armored: &str) -> Fingerprint {
+        let hash = blake3::hash(armored.as_bytes());
+
+        let mut digest = [0; LENGTH];
+        digest.copy_from_slice(&hash.as_bytes()[..LENGTH]);
+
+        Fingerprint { digest }
+    }
+
+    /// Hex in groups of four digits
+    pub fn hex_groups(&self) -> Vec<String> {
+        self.digest
+            .chunks(2)
+            .map(|chunk| chunk.iter().map(|b| format!("{b:02x}")).collect())
+            .collect()
+    }
+
+    /// Words from the diceware wordlist, easier to compare by reading aloud
+    pub fn words(&self) -> Vec<&'static str> {
+        let wordlist = diceware::words();
+        let count = wordlist.len() as u64;
+
+        let mut value = u64::from_be_bytes(self.digest[..8].try_into().expect("8 bytes"));
+        (0..WORDS)
+            .map(|_| {
+                let word = wordlist[(value % count) as usize];
+                value /= count;
+                word
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({})",
+            self.hex_groups().join("-"),
+            self.words().join(" ")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fingerprint() {
+        let fingerprint = Fingerprint::of_ciphertext("ciphertext");
+
+        let groups = fingerprint.hex_groups();
+        assert_eq!(groups.len(), 5);
+        assert!(groups.iter().all(|g| g.len() == 4));
+
+        let words = fingerprint.words();
+        assert_eq!(words.len(), 4);
+
+        assert_eq!(
+            fingerprint.to_string(),
+            format!("{} ({})", groups.join("-"), words.join(" "))
+        );
+    }
+
+    #[test]
+    fn test_known_value() {
+        // BLAKE3("") = af1349b9f5f9a1a6a0404dea36dcc949...
+        let fingerprint = Fingerprint::of_ciphertext("");
+        assert_eq!(
+            fingerprint.hex_groups(),
+            vec!["af13", "49b9", "f5f9", "a1a6", "a040"]
+        );
+    }
+
+    #[test]
+    fn test_different_ciphertexts() {
+        assert_ne!(
+            Fingerprint::of_ciphertext("one"),
+            Fingerprint::of_ciphertext("two")
+        );
+    }
+}
@@ -0,0 +1,185 @@
+//! Detection and compaction of age identity files (age-keygen output, SOPS age
+//! keys)
+use std::{
+    io::{self, Write},
+    str::FromStr,
+};
+
+use crate::memory::SecretBytes;
+
+/// One identity and the comments about it that are worth keeping
+#[derive(Debug)]
+struct Entry<'a> {
+    /// Creation date from the `# created:` comment
+    created: Option<&'a str>,
+
+    /// Derived from X25519 identities, or from the `# public key:` or
+    /// `# recipient:` comment for plugin identities
+    public_key: Option<String>,
+
+    /// The identity itself (AGE-SECRET-KEY-1… or AGE-PLUGIN-…)
+    secret: &'a str,
+}
+
+/// An age identity file in the plaintext
+#[derive(Debug)]
+pub struct IdentityFile<'a> {
+    entries: Vec<Entry<'a>>,
+}
+
+impl<'a> IdentityFile<'a> {
+    /// Parse the plaintext as an identity file: only comments, blank lines, and
+    /// at least one identity. Returns None for anything else.
+    pub fn parse(plaintext: &'a [u8]) -> Option<IdentityFile<'a>> {
+        let text = std::str::from_utf8(plaintext).ok()?;
+
+        let mut entries = vec![];
+        let mut created = None;
+        let mut public_key = None;
+
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(comment) = line.strip_prefix('#') {
+                let comment = comment.trim();
+                if let Some(value) = comment.strip_prefix("created:") {
+                    created = Some(value.trim());
+                } else if let Some(value) = comment
+                    .strip_prefix("public key:")
+                    .or_else(|| comment.strip_prefix("recipient:"))
+                {
+                    public_key = Some(value.trim().to_string());
+                }
+                continue;
+            }
+
+            let commented_key = public_key.take();
+            let public_key = if line.starts_with("AGE-SECRET-KEY-1") {
+                let identity = age::x25519::Identity::from_str(line).ok()?;
+                Some(identity.to_public().to_string())
+            } else if line.starts_with("AGE-PLUGIN-") {
+                commented_key
+            } else {
+                return None;
+            };
+
+            entries.push(Entry {
+                created: created.take(),
+                public_key,
+                secret: line,
+            });
+        }
+
+        if entries.is_empty() {
+            return None;
+        }
+
+        Some(IdentityFile { entries })
+    }
+
+    /// Number of identities in the file
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Never true for a parsed file, there's always at least one identity
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Public keys of the identities, where known
+    pub fn public_keys(&self) -> Vec<String> {
+        self.entries
+            .iter()
+            .filter_map(|entry| entry.public_key.clone())
+            .collect()
+    }
+
+    /// The identities with only their creation dates and public keys as
+    /// comments. It's still a valid identity file.
+    pub fn compact(&self) -> Result<SecretBytes, io::Error> {
+        let mut compacted = SecretBytes::with_capacity(128 * self.entries.len())?;
+
+        for entry in &self.entries {
+            if let Some(created) = entry.created {
+                writeln!(compacted, "# created: {created}")?;
+            }
+            if let Some(public_key) = &entry.public_key {
+                writeln!(compacted, "# public key: {public_key}")?;
+            }
+            writeln!(compacted, "{}", entry.secret)?;
+        }
+
+        Ok(compacted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use age::secrecy::ExposeSecret;
+
+    use super::*;
+
+    const IDENTITY: &str = include_str!("../tests/data/identity.txt");
+    const PUBLIC_KEY: &str = "age1g3jnt5c79rs5nulvthlswd4x0psepldrthmsp6t6y665ff5l7e4q38r8sy";
+
+    #[test]
+    fn test_keys_file() -> Result<(), io::Error> {
+        let second = age::x25519::Identity::generate();
+        let text = format!(
+            "# SOPS keys for the staging cluster\n\
+             # created: 2024-01-31T12:00:00+01:00\n\
+             # public key: {PUBLIC_KEY}\n\
+             {}\n\
+             \n\
+             # created: 2024-02-01T08:30:00Z\r\n\
+             {}\r\n",
+            IDENTITY
+                .lines()
+                .find(|line| line.starts_with("AGE-SECRET-KEY-1"))
+                .unwrap(),
+            second.to_string().expose_secret(),
+        );
+
+        let identity_file = IdentityFile::parse(text.as_bytes()).unwrap();
+        assert_eq!(identity_file.len(), 2);
+        assert_eq!(
+            identity_file.public_keys(),
+            vec![PUBLIC_KEY.to_string(), second.to_public().to_string()]
+        );
+
+        let compacted = identity_file.compact()?;
+        let compacted = std::str::from_utf8(&compacted).unwrap();
+        assert!(!compacted.contains("staging"));
+        assert!(compacted.starts_with("# created: 2024-01-31T12:00:00+01:00\n"));
+        assert!(compacted.contains("# created: 2024-02-01T08:30:00Z\n"));
+        assert!(compacted.contains(&format!("# public key: {}\n", second.to_public())));
+
+        // Still a valid identity file
+        let identities = IdentityFile::parse(compacted.as_bytes()).unwrap();
+        assert_eq!(identities.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_plugin_identity() {
+        let text = "# recipient: age1yubikey1qexample\nAGE-PLUGIN-YUBIKEY-1EXAMPLE\n";
+
+        let identity_file = IdentityFile::parse(text.as_bytes()).unwrap();
+        assert_eq!(identity_file.public_keys(), vec!["age1yubikey1qexample"]);
+    }
+
+    #[test]
+    fn test_not_an_identity_file() {
+        assert!(IdentityFile::parse(b"Hello").is_none());
+        assert!(IdentityFile::parse(b"# Only a comment\n").is_none());
+        assert!(IdentityFile::parse(b"AGE-SECRET-KEY-1INVALID\n").is_none());
+        assert!(IdentityFile::parse(&[0xff, 0xfe]).is_none());
+
+        let text = format!("{IDENTITY}\nSome other text\n");
+        assert!(IdentityFile::parse(text.as_bytes()).is_none());
+    }
+}
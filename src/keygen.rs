@@ -0,0 +1,94 @@
+//! Fresh age identities for paper key sheets
+use std::{
+    io::{self, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use age::secrecy::ExposeSecret;
+
+use crate::memory::SecretBytes;
+
+/// Generate an X25519 identity. Returns the identity file in the same format as
+/// age-keygen, and the public key.
+pub fn generate() -> Result<(SecretBytes, String), io::Error> {
+    let identity = age::x25519::Identity::generate();
+    let public_key = identity.to_public().to_string();
+
+    let mut identity_file = SecretBytes::with_capacity(256)?;
+    writeln!(identity_file, "# created: {}", timestamp(SystemTime::now()))?;
+    writeln!(identity_file, "# public key: {public_key}")?;
+    writeln!(identity_file, "{}", identity.to_string().expose_secret())?;
+
+    Ok((identity_file, public_key))
+}
+
+/// RFC 3339 timestamp in UTC (e.g. 2024-01-31T12:00:00Z)
+fn timestamp(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+
+    let days = (seconds / 86400) as i64;
+    let (year, month, day) = civil_from_days(days);
+    let seconds = seconds % 86400;
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Gregorian calendar date from the number of days since 1970-01-01, see
+/// <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_generate() -> Result<(), Box<dyn std::error::Error>> {
+        let (identity_file, public_key) = generate()?;
+        assert!(public_key.starts_with("age1"));
+
+        let text = std::str::from_utf8(&identity_file)?;
+        assert!(text.starts_with("# created: "));
+        assert!(text.contains(&format!("# public key: {public_key}\n")));
+
+        let identities = age::IdentityFile::from_buffer(text.as_bytes())?.into_identities()?;
+        assert_eq!(identities.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_timestamp() {
+        assert_eq!(timestamp(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        assert_eq!(
+            timestamp(UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+            "2023-11-14T22:13:20Z"
+        );
+        assert_eq!(
+            timestamp(UNIX_EPOCH + Duration::from_secs(951_782_400)),
+            "2000-02-29T00:00:00Z"
+        );
+    }
+}
@@ -6,21 +6,38 @@
 
 use std::{
     env,
-    fs::File,
+    fs::{self, File, OpenOptions},
     io::{self, stdin, BufReader, BufWriter, Read, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
+    time::Duration,
 };
 
-use age::secrecy::{ExposeSecret, Secret, SecretString};
+use age::secrecy::{ExposeSecret, SecretString};
 use clap::Parser;
 use printpdf::LineDashPattern;
 use qrcode::types::QrError;
-use rpassword::prompt_password;
+use rand::{rngs::OsRng, RngCore};
+use zeroize::Zeroizing;
 
+pub mod bip39;
 pub mod builder;
 pub mod cli;
+pub mod compression;
+pub mod decryption;
+pub mod diceware;
 pub mod encryption;
+pub mod fingerprint;
+pub mod identity_file;
+pub mod keygen;
+pub mod memory;
+pub mod openpgp;
 pub mod page;
+pub mod passphrase;
+pub mod payload;
+pub mod pinentry;
+pub mod recipients;
+pub mod shamir;
+pub mod signature;
 
 #[macro_use]
 extern crate log;
@@ -28,6 +45,29 @@ extern crate log;
 /// Maximum length of the document title
 const TITLE_MAX_LEN: usize = 64;
 
+/// Plaintext and the sheet specific annotations for one sheet
+struct Sheet {
+    /// Plaintext to encrypt for the sheet
+    plaintext: memory::SecretBytes,
+
+    /// Lines of text next to the QR code
+    side_note: Vec<String>,
+
+    /// Small print specific to the sheet
+    details: Vec<String>,
+}
+
+impl Sheet {
+    /// Sheet without any annotations
+    fn new(plaintext: memory::SecretBytes) -> Sheet {
+        Sheet {
+            plaintext,
+            side_note: vec![],
+            details: vec![],
+        }
+    }
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = cli::Args::parse();
 
@@ -41,6 +81,230 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         return Ok(());
     }
 
+    memory::set_paranoid(args.paranoid);
+    if let Err(e) = memory::disable_core_dumps() {
+        if args.paranoid {
+            error!("Could not disable core dumps: {e}");
+            std::process::exit(exitcode::OSERR);
+        }
+        warn!("Could not disable core dumps: {e}");
+    }
+
+    match args.command {
+        Some(cli::Command::Decrypt(decrypt_args)) => decrypt(&decrypt_args),
+        Some(cli::Command::Combine(combine_args)) => combine(&combine_args),
+        Some(cli::Command::Rewrap(rewrap_args)) => rewrap(*rewrap_args),
+        Some(cli::Command::Keygen(keygen_args)) => keygen(*keygen_args),
+        Some(cli::Command::RestoreOpenpgp(restore_args)) => restore_openpgp(&restore_args),
+        None => create(args.create),
+    }
+}
+
+/// Encrypt the input and lay it out on one or more sheets
+fn create(args: cli::CreateArgs) -> Result<(), Box<dyn std::error::Error>> {
+    check_create_args(&args);
+
+    let path = match args.input.clone() {
+        Some(p) => p,
+        None => PathBuf::from("-"),
+    };
+
+    let mut reader: BufReader<Box<dyn Read>> = {
+        if path.as_os_str() == "-" {
+            BufReader::new(Box::new(stdin().lock()))
+        } else if path.is_file() {
+            let size = path.metadata()?.len();
+            if size >= 2048 && !args.compress && !args.openpgp && args.key_sheet.is_none() {
+                warn!("File too large ({size:?} bytes). The maximum file size is about 1.9 KiB.");
+            }
+            BufReader::new(Box::new(File::open(&path).unwrap()))
+        } else {
+            error!("File not found: {}", path.display());
+            std::process::exit(exitcode::NOINPUT);
+        }
+    };
+
+    if let Some(path) = args.key_sheet.clone() {
+        return write_key_sheet(args.sheet, &mut reader, &path);
+    }
+
+    let mut plaintext = secret_or_exit(memory::SecretBytes::read_from(&mut reader));
+
+    if args.openpgp {
+        let minimized = match openpgp::minimize(&plaintext) {
+            Ok(minimized) => minimized,
+            Err(e) if memory::is_lock_error(&e) => secret_or_exit(Err(e)),
+            Err(e) => {
+                error!("{e}");
+                std::process::exit(exitcode::DATAERR);
+            }
+        };
+        info!(
+            "Minimized the OpenPGP secret key from {} to {} bytes",
+            plaintext.len(),
+            minimized.len()
+        );
+        plaintext = minimized;
+    } else if openpgp::is_secret_key(&plaintext) {
+        warn!("The input looks like an OpenPGP secret key, use --openpgp to only keep the secret key material");
+    }
+
+    if let Some(path) = args.break_glass.clone() {
+        return write_tiers(args, plaintext, &path);
+    }
+
+    let plaintext = check_mnemonic(plaintext, args.bip39_entropy, args.allow_invalid_mnemonic);
+    let (plaintext, public_keys) = compact_identity_file(plaintext);
+
+    let encryption = prepare_encryption(&args.sheet)?;
+    write_sheets(
+        args.sheet,
+        args.compress,
+        encryption,
+        Sheet::new(plaintext),
+        &public_keys,
+    )
+}
+
+/// Encrypt the input to a new age file with a freshly generated identity, and
+/// lay out only the identity on the sheet. The age file only replaces an
+/// existing one once the sheet is written.
+fn write_key_sheet(
+    args: cli::SheetArgs,
+    reader: &mut dyn io::BufRead,
+    path: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let encryption = prepare_encryption(&args)?;
+
+    let (identity_file, public_key) = secret_or_exit(keygen::generate());
+    let recipient: age::x25519::Recipient = public_key.parse()?;
+
+    let encrypted = match encryption::encrypt_to_file(reader, &recipient, path) {
+        Ok(encrypted) => encrypted,
+        Err(e) => {
+            error!("Could not encrypt the input to {}: {e}", path.display());
+            std::process::exit(exitcode::IOERR);
+        }
+    };
+
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string());
+    let hash = encrypted.sha256_hex();
+    info!(
+        "Encrypted the input to {} ({} bytes, SHA-256 {hash})",
+        path.display(),
+        encrypted.size
+    );
+
+    let sheet = Sheet {
+        plaintext: identity_file,
+        side_note: vec![
+            String::from("Key sheet"),
+            name.clone(),
+            format!("{} bytes", encrypted.size),
+            format!("SHA-256 {}", &hash[..8]),
+        ],
+        details: vec![
+            format!("Key sheet for {name}: {} bytes", encrypted.size),
+            format!("SHA-256 of {name}: {hash}"),
+            format!(
+                "Decrypt the sheet to key.txt, then decrypt the file with age -d -i key.txt {name}"
+            ),
+        ],
+    };
+
+    write_sheets(args, false, encryption, sheet, &[])?;
+
+    if let Err(e) = encrypted.persist() {
+        error!("Could not write {}: {e}", path.display());
+        std::process::exit(exitcode::IOERR);
+    }
+
+    Ok(())
+}
+
+/// Check the checksum of a BIP39 mnemonic. Returns the plaintext, or the
+/// compact form of the mnemonic if `compact` is set. An invalid mnemonic is
+/// refused unless `allow_invalid` is set, then it's encrypted as is.
+fn check_mnemonic(
+    plaintext: memory::SecretBytes,
+    compact: bool,
+    allow_invalid: bool,
+) -> memory::SecretBytes {
+    let entropy = match bip39::detect(&plaintext) {
+        Ok(Some(entropy)) => entropy,
+        Ok(None) => {
+            if compact {
+                warn!("The input isn't a BIP39 mnemonic, ignoring --bip39-entropy");
+            }
+            return plaintext;
+        }
+        Err(e) if allow_invalid => {
+            warn!("{e}");
+            warn!("Encrypting the invalid mnemonic as it is");
+            return plaintext;
+        }
+        Err(e) => {
+            error!("{e}");
+            error!("Refusing to print a mnemonic with an invalid checksum, use --allow-invalid-mnemonic to encrypt it as it is");
+            std::process::exit(exitcode::DATAERR);
+        }
+    };
+
+    info!(
+        "The input is a BIP39 mnemonic with a valid checksum ({} bits of entropy)",
+        entropy.len() * 8
+    );
+
+    if !compact {
+        return plaintext;
+    }
+
+    let compacted = secret_or_exit(bip39::compact(&entropy));
+    info!(
+        "Storing the entropy instead of the words ({} instead of {} bytes)",
+        compacted.len(),
+        plaintext.len()
+    );
+
+    compacted
+}
+
+/// Strip the comments from an age identity file, except for the creation dates
+/// and the public keys. Returns the plaintext and the public keys to print in
+/// the clear, which are empty for any other plaintext.
+fn compact_identity_file(plaintext: memory::SecretBytes) -> (memory::SecretBytes, Vec<String>) {
+    let Some(identity_file) = identity_file::IdentityFile::parse(&plaintext) else {
+        return (plaintext, vec![]);
+    };
+
+    let public_keys = identity_file.public_keys();
+    info!(
+        "The input is an age identity file with {} identities ({} public keys known)",
+        identity_file.len(),
+        public_keys.len()
+    );
+
+    let compacted = secret_or_exit(identity_file.compact());
+    if compacted.len() >= plaintext.len() {
+        debug!("Keeping the identity file as it is, compacting it wouldn't make it smaller");
+        return (plaintext, public_keys);
+    }
+
+    info!(
+        "Compacted the identity file from {} to {} bytes",
+        plaintext.len(),
+        compacted.len()
+    );
+
+    (compacted, public_keys)
+}
+
+/// Check the arguments for new sheets before reading anything, or log the
+/// error and exit
+fn check_sheet_args(args: &cli::SheetArgs) {
     if args.title.len() > TITLE_MAX_LEN {
         error!(
             "The title cannot be longer than {} characters",
@@ -49,7 +313,21 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         std::process::exit(exitcode::DATAERR);
     }
 
-    let output = args.output;
+    if let (Some(shares), Some(threshold)) = (args.shares, args.threshold) {
+        if threshold > shares {
+            error!(
+                "The threshold ({threshold}) cannot be larger than the number of shares ({shares})"
+            );
+            std::process::exit(exitcode::USAGE);
+        }
+    }
+
+    if args.custodians.len() > usize::from(args.shares.unwrap_or(0)) {
+        error!("There are more custodians than shares");
+        std::process::exit(exitcode::USAGE);
+    }
+
+    let output = &args.output;
     if output.exists() {
         if args.force {
             warn!("Overwriting existing output file: {}", output.display());
@@ -59,55 +337,890 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
-    let path = match args.input {
-        Some(p) => p,
-        None => PathBuf::from("-"),
+    if let Some(path) = &args.passphrase_output {
+        if same_output(path, output) {
+            error!("The passphrase can't be written to the same file as the sheet");
+            std::process::exit(exitcode::USAGE);
+        }
+        if path.exists() {
+            if args.force {
+                warn!("Overwriting existing passphrase file: {}", path.display());
+            } else {
+                error!("Passphrase file already exists: {}", path.display());
+                std::process::exit(exitcode::CANTCREAT);
+            }
+        }
+    }
+
+    if let Some(path) = &args.armor_output {
+        if same_output(path, output)
+            || args
+                .passphrase_output
+                .as_ref()
+                .is_some_and(|passphrase_output| same_output(path, passphrase_output))
+        {
+            error!(
+                "The ciphertexts can't be written to the same file as the sheet or the passphrase"
+            );
+            std::process::exit(exitcode::USAGE);
+        }
+        if path.exists() {
+            if args.force {
+                warn!("Overwriting existing ciphertext file: {}", path.display());
+            } else {
+                error!("Ciphertext file already exists: {}", path.display());
+                std::process::exit(exitcode::CANTCREAT);
+            }
+        }
+    }
+
+    if !args.holders.is_empty() && get_passphrase_source(&args.passphrase).is_some() {
+        error!("Each holder enters their passphrase at the prompt, it can't be read from a file");
+        std::process::exit(exitcode::USAGE);
+    }
+}
+
+/// Check the arguments for creating a sheet from an input, or log the error
+/// and exit
+fn check_create_args(args: &cli::CreateArgs) {
+    check_sheet_args(&args.sheet);
+
+    if let Some(path) = &args.key_sheet {
+        if path.exists() && !args.sheet.force {
+            error!("Encrypted file already exists: {}", path.display());
+            std::process::exit(exitcode::CANTCREAT);
+        }
+    }
+
+    #[cfg(unix)]
+    check_stdin(&args.sheet.passphrase, args.input.as_deref());
+}
+
+/// Check that the passphrase and the input aren't both read from STDIN, or log
+/// the error and exit
+#[cfg(unix)]
+fn check_stdin(passphrase: &cli::PassphraseArgs, input: Option<&Path>) {
+    if passphrase.fd == Some(0) && input.map_or(true, |p| p.as_os_str() == "-") {
+        error!("Can't read both the passphrase and the input from STDIN");
+        std::process::exit(exitcode::USAGE);
+    }
+}
+
+/// How the sheets are encrypted. Collected (and the passphrases checked) before
+/// any output is written.
+struct Encryption {
+    /// Recipients to encrypt to instead of a passphrase
+    recipients: Vec<recipients::Recipient>,
+
+    /// Recipients of the inner layer of a two factor sheet
+    second_factors: Vec<recipients::Recipient>,
+
+    /// The passphrases (a single one, or one per holder) and the work factor,
+    /// unless encrypting to recipients
+    passphrases: Option<(Vec<SecretString>, u8)>,
+
+    /// Generated passphrase to write to its own file, and its entropy in bits
+    passphrase_stub: Option<(SecretString, f64)>,
+
+    /// Key to sign the ciphertexts with
+    signing_key: Option<ssh_key::PrivateKey>,
+
+    /// Small print about the encryption
+    details: Vec<String>,
+}
+
+/// Collect the recipients, the passphrases, and the signing key for the new
+/// sheets, or log the error and exit
+fn prepare_encryption(args: &cli::SheetArgs) -> Result<Encryption, Box<dyn std::error::Error>> {
+    let passphrase_source = get_passphrase_source(&args.passphrase);
+    let mut details = vec![];
+
+    let recipients = match get_recipients(&args.recipients, &args.recipients_files, args.ssh_keys) {
+        Ok(recipients) => recipients,
+        Err(e) => exit_with_recipients_error(e),
     };
-    let mut reader: BufReader<Box<dyn Read>> = {
-        if path == PathBuf::from("-") {
-            BufReader::new(Box::new(stdin().lock()))
-        } else if path.is_file() {
-            let size = path.metadata()?.len();
-            if size >= 2048 {
-                warn!("File too large ({size:?} bytes). The maximum file size is about 1.9 KiB.");
+
+    let second_factors =
+        match get_recipients(&args.second_factors, &args.second_factors_files, false) {
+            Ok(recipients) => recipients,
+            Err(e) => exit_with_recipients_error(e),
+        };
+    if !second_factors.is_empty() {
+        info!(
+            "Two factors: the passphrase and an identity for one of {} recipient(s)",
+            second_factors.len()
+        );
+        details.push(String::from(
+            "Two factors: decrypt with the passphrase, then with an identity for one of these recipients:",
+        ));
+        details.extend(
+            second_factors
+                .iter()
+                .map(|r| format!("  {}", r.fingerprint)),
+        );
+    }
+
+    let signing_key = match &args.sign_with {
+        Some(path) => {
+            let prompter = passphrase::Prompter::detect(args.passphrase.pinentry.clone());
+            match signature::read_signing_key(path, &prompter) {
+                Ok(key) => {
+                    let key_fingerprint = key.fingerprint(ssh_key::HashAlg::Sha256);
+                    info!("Signing with {key_fingerprint}");
+                    details.push(format!("Signed by {key_fingerprint}"));
+                    Some(key)
+                }
+                Err(e) => {
+                    error!("{e}");
+                    match e.kind() {
+                        io::ErrorKind::NotFound => std::process::exit(exitcode::NOINPUT),
+                        io::ErrorKind::PermissionDenied => std::process::exit(exitcode::NOPERM),
+                        _ => std::process::exit(exitcode::DATAERR),
+                    }
+                }
             }
-            BufReader::new(Box::new(File::open(&path).unwrap()))
+        }
+        None => None,
+    };
+
+    // Generated passphrase to print on a separate page
+    let mut passphrase_stub = None;
+
+    let passphrases = if recipients.is_empty() {
+        let passphrases = if args.holders.is_empty() {
+            let (passphrase, entropy_bits) = match args.generate_passphrase {
+                Some(words) => {
+                    let words = usize::from(words);
+                    let entropy_bits = diceware::entropy_bits(words);
+                    info!(
+                        "Generated a {words} word passphrase ({entropy_bits:.1} bits of entropy)"
+                    );
+                    (diceware::generate(words), Some(entropy_bits))
+                }
+                None => (
+                    passphrase_or_exit(passphrase::get_passphrase(
+                        passphrase_source.as_ref(),
+                        &passphrase::Prompter::detect(args.passphrase.pinentry.clone()),
+                    ))?,
+                    None,
+                ),
+            };
+
+            check_passphrase(&passphrase, args);
+
+            if let Some(entropy_bits) = entropy_bits {
+                if args.passphrase_output.is_some() {
+                    passphrase_stub = Some((passphrase.clone(), entropy_bits));
+                } else {
+                    passphrase::write_to_tty(
+                        format!(
+                            "Passphrase ({entropy_bits:.1} bits of entropy): {}",
+                            passphrase.expose_secret()
+                        )
+                        .as_str(),
+                    )?;
+                }
+            }
+
+            vec![passphrase]
         } else {
-            error!("File not found: {}", path.display());
+            holder_passphrases(args)?
+        };
+
+        let (work_factor, work_factor_details) = get_work_factor(args);
+        details.push(work_factor_details);
+
+        Some((passphrases, work_factor))
+    } else {
+        None
+    };
+
+    Ok(Encryption {
+        recipients,
+        second_factors,
+        passphrases,
+        passphrase_stub,
+        signing_key,
+        details,
+    })
+}
+
+/// Encrypt the plaintext of the sheet and lay it out on one or more sheets. The
+/// side note and the details of the sheet are added to every sheet.
+fn write_sheets(
+    args: cli::SheetArgs,
+    compress: bool,
+    encryption: Encryption,
+    sheet: Sheet,
+    public_keys: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let output = args.output.clone();
+    let Encryption {
+        recipients,
+        second_factors,
+        passphrases,
+        passphrase_stub,
+        signing_key,
+        details: encryption_details,
+    } = encryption;
+
+    // The details are the small print above the footer
+    let Sheet {
+        plaintext,
+        side_note: common_note,
+        mut details,
+    } = sheet;
+
+    let (plaintext, plaintext_details) = prepare_plaintext(plaintext, compress);
+    details.extend(plaintext_details);
+
+    let fingerprints: Vec<String> = recipients.iter().map(|r| r.fingerprint.clone()).collect();
+
+    let two_factor_note = if second_factors.is_empty() {
+        vec![]
+    } else {
+        vec![
+            String::from("Two factors"),
+            String::from("Passphrase AND"),
+            String::from("an identity for"),
+            String::from("a recipient below"),
+        ]
+    };
+
+    // Public keys of the identities in the plaintext, printed in the clear
+    let public_key_note = match public_keys {
+        [] => vec![],
+        [public_key] => {
+            let mut note = vec![String::from("Public key")];
+            note.extend(
+                public_key
+                    .as_bytes()
+                    .chunks(16)
+                    .map(|chunk| String::from_utf8_lossy(chunk).into_owned()),
+            );
+            note
+        }
+        // Shortened to fit the column, the full keys are in the small print
+        _ => {
+            let mut note = vec![String::from("Public keys")];
+            note.extend(public_keys.iter().map(|key| abbreviate_public_key(key)));
+            note
+        }
+    };
+    details.extend(public_keys.iter().map(|key| format!("Public key: {key}")));
+    details.extend(encryption_details);
+
+    // Split the plaintext into Shamir shares, copy it for each holder, or keep
+    // it on a single sheet
+    let sheets = match (args.shares, args.threshold) {
+        _ if !args.holders.is_empty() => {
+            let mut set_id = [0; 4];
+            OsRng.fill_bytes(&mut set_id);
+            let set_id: String = set_id.iter().map(|b| format!("{b:02x}")).collect();
+            info!(
+                "One sheet for each of the {} holders (set ID: {set_id})",
+                args.holders.len()
+            );
+
+            args.holders
+                .iter()
+                .map(|holder| holder_sheet(&plaintext, holder, &set_id, args.holders.len()))
+                .collect()
+        }
+        (Some(total), Some(threshold)) => {
+            let shares = shamir::split(&plaintext, threshold, total)?;
+            info!(
+                "Split the plaintext into {total} shares (threshold: {threshold}, set ID: {})",
+                shares[0].set_id_hex()
+            );
+
+            shares
+                .iter()
+                .map(|share| {
+                    let custodian = args.custodians.get(usize::from(share.index) - 1);
+                    share_sheet(share, custodian)
+                })
+                .collect()
+        }
+        // A share from an old sheet, rewrapped as it is
+        _ if shamir::is_share(&plaintext) => {
+            let share = shamir::Share::from_bytes(&plaintext)?;
+            vec![share_sheet(&share, None)]
+        }
+        _ => vec![Sheet::new(plaintext)],
+    };
+
+    let mut pdf = builder::Document::new(args.title.clone(), args.page_size.clone())?;
+    let mut armored = vec![];
+
+    for (i, sheet) in sheets.into_iter().enumerate() {
+        // Each holder has their own passphrase, other sheets share the same one
+        let passphrase = passphrases.as_ref().map(|(passphrases, work_factor)| {
+            (&passphrases[i.min(passphrases.len() - 1)], work_factor)
+        });
+
+        // Encrypt the plaintext to a ciphertext using the recipients or the passphrase...
+        let result = match passphrase {
+            Some((passphrase, work_factor)) if second_factors.is_empty() => {
+                encryption::encrypt_plaintext(
+                    &mut &sheet.plaintext[..],
+                    passphrase.clone(),
+                    *work_factor,
+                )
+            }
+            Some((passphrase, work_factor)) => encryption::encrypt_two_factor(
+                &mut &sheet.plaintext[..],
+                &second_factors,
+                passphrase.clone(),
+                *work_factor,
+            ),
+            None => encryption::encrypt_to_recipients(&mut &sheet.plaintext[..], &recipients),
+        };
+        let (plaintext_len, encrypted) = match result {
+            Ok(result) => result,
+            Err(error) => match error.downcast_ref::<age::EncryptError>() {
+                Some(age::EncryptError::Plugin(errors)) => {
+                    for e in errors {
+                        error!("{e}");
+                    }
+                    std::process::exit(exitcode::PROTOCOL);
+                }
+                Some(age::EncryptError::Io(e)) => {
+                    error!("{e}");
+                    std::process::exit(exitcode::IOERR);
+                }
+                _ => return Err(error),
+            },
+        };
+
+        info!("Plaintext length: {plaintext_len:?} bytes");
+        info!("Encrypted length: {:?} bytes", encrypted.len());
+
+        let signature = signing_key
+            .as_ref()
+            .map(|key| signature::sign(key, &encrypted))
+            .transpose()?;
+
+        if i > 0 {
+            pdf.add_page();
+        }
+
+        let mut sheet_details = details.clone();
+        sheet_details.extend(sheet.details);
+        if let Some((passphrase, _)) = passphrase {
+            sheet_details.push(passphrase::charset_hint(passphrase));
+        }
+
+        let mut side_note = sheet.side_note;
+        for note in [&common_note, &two_factor_note, &public_key_note] {
+            if note.is_empty() {
+                continue;
+            }
+            if !side_note.is_empty() {
+                side_note.push(String::new());
+            }
+            side_note.extend(note.iter().cloned());
+        }
+
+        armored.push(encrypted.clone());
+        insert_sheet(
+            &pdf,
+            &args,
+            encrypted,
+            &fingerprints,
+            side_note,
+            sheet_details,
+            signature.as_deref(),
+        )?;
+
+        if let [public_key] = public_keys {
+            layout_or_exit(pdf.insert_public_key_qr_code(public_key));
+        }
+    }
+
+    save_pdf(pdf, &output)?;
+    if let Some(path) = &args.armor_output {
+        save_armor(&armored, path)?;
+    }
+
+    // The generated passphrase goes to its own file, so that it can be stored
+    // apart from the sheet
+    if let (Some((passphrase, entropy_bits)), Some(path)) =
+        (passphrase_stub, &args.passphrase_output)
+    {
+        let stub = builder::Document::new(args.title.clone(), args.page_size.clone())?;
+        stub.insert_title_text(args.title.clone());
+        stub.insert_passphrase_stub(&passphrase, entropy_bits);
+
+        info!("Writing the generated passphrase to {}", path.display());
+        save_pdf(stub, path)?;
+    }
+
+    Ok(())
+}
+
+/// Check if two output paths are the same file, even if they don't exist yet
+fn same_output(a: &Path, b: &Path) -> bool {
+    if a == b {
+        return true;
+    }
+
+    let resolve = |path: &Path| -> Option<PathBuf> {
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        Some(parent.canonicalize().ok()?.join(path.file_name()?))
+    };
+
+    matches!((resolve(a), resolve(b)), (Some(a), Some(b)) if a == b)
+}
+
+/// Write the PDF to the output file, or to STDOUT for -
+fn save_pdf(pdf: builder::Document, output: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    if output.as_os_str() == "-" {
+        debug!("Writing to STDOUT");
+        let bytes = pdf.doc.save_to_bytes()?;
+        io::stdout().write_all(&bytes)?;
+    } else {
+        debug!("Writing to file: {}", output.to_string_lossy());
+        let file = File::create(output)?;
+        pdf.doc.save(&mut BufWriter::new(file))?;
+    }
+
+    Ok(())
+}
+
+/// Write the ASCII armored ciphertexts to a file, one after the other
+fn save_armor(armored: &[String], path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    debug!(
+        "Writing the ciphertexts to file: {}",
+        path.to_string_lossy()
+    );
+    fs::write(path, armored.concat())?;
+
+    Ok(())
+}
+
+/// Add the notes about the plaintext to the details, and compress it if
+/// requested
+fn prepare_plaintext(
+    mut plaintext: memory::SecretBytes,
+    compress: bool,
+) -> (memory::SecretBytes, Vec<String>) {
+    let mut details = vec![];
+
+    if bip39::is_compact(&plaintext) {
+        details.push(String::from(
+            "The plaintext is the entropy of a BIP39 mnemonic (after a \\0PAB marker), paper-age decrypt turns it back into the words",
+        ));
+    }
+
+    if openpgp::is_minimized(&plaintext) {
+        details.push(String::from(
+            "OpenPGP secret key material only: restore it with paper-age restore-openpgp --public-key <PUBLIC_KEY>",
+        ));
+    }
+
+    if compress {
+        match secret_or_exit(compression::compress(&plaintext)) {
+            Some(compressed) => {
+                let saved = plaintext.len() - compressed.len();
+                info!(
+                    "Compressed the plaintext from {} to {} bytes (saved {saved} bytes, {:.0}%)",
+                    plaintext.len(),
+                    compressed.len(),
+                    saved as f64 / plaintext.len() as f64 * 100.0
+                );
+                details.push(String::from(
+                    "The plaintext is compressed (raw deflate after a \\0PAZ marker)",
+                ));
+                plaintext = compressed;
+            }
+            None => info!("Compression wouldn't make the plaintext smaller, skipping it"),
+        }
+    }
+
+    (plaintext, details)
+}
+
+/// The scrypt work factor from the arguments, calibrated on this machine for
+/// --work-factor-target, or the default. Also returns the line for the details.
+fn get_work_factor(args: &cli::SheetArgs) -> (u8, String) {
+    let (work_factor, duration) = match (args.work_factor, args.work_factor_target) {
+        (None, Some(seconds)) => encryption::calibrate_work_factor(Duration::from_secs(seconds)),
+        (work_factor, _) => {
+            let work_factor = work_factor.unwrap_or(encryption::DEFAULT_WORK_FACTOR);
+            (work_factor, encryption::estimate_duration(work_factor))
+        }
+    };
+
+    let estimate = format_duration(duration);
+    info!("scrypt work factor: 2^{work_factor} (about {estimate})");
+
+    if work_factor > encryption::AGE_DEFAULT_MAX_WORK_FACTOR {
+        warn!(
+            "The age CLI refuses work factors above {} by default, decrypt the sheet with paper-age or another age implementation without that limit",
+            encryption::AGE_DEFAULT_MAX_WORK_FACTOR
+        );
+    }
+
+    (
+        work_factor,
+        format!(
+            "scrypt work factor: 2^{work_factor} (about {estimate} to decrypt on the machine that made this sheet)"
+        ),
+    )
+}
+
+/// Lay out the everyday tier (the input) and the break-glass tier side by side
+/// on a single sheet, each encrypted with its own passphrase
+fn write_tiers(
+    args: cli::CreateArgs,
+    everyday: memory::SecretBytes,
+    break_glass_path: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let break_glass = match File::open(break_glass_path) {
+        Ok(mut file) => secret_or_exit(memory::SecretBytes::read_from(&mut file)),
+        Err(e) => {
+            error!(
+                "Can't read the break-glass input {}: {e}",
+                break_glass_path.display()
+            );
             std::process::exit(exitcode::NOINPUT);
         }
     };
 
-    let passphrase = get_passphrase()?;
+    // Check both inputs before asking for any passphrases
+    let [everyday, break_glass] = [everyday, break_glass].map(|plaintext| {
+        compact_identity_file(check_mnemonic(
+            plaintext,
+            args.bip39_entropy,
+            args.allow_invalid_mnemonic,
+        ))
+    });
 
-    // Encrypt the plaintext to a ciphertext using the passphrase...
-    let (plaintext_len, encrypted) = encryption::encrypt_plaintext(&mut reader, passphrase)?;
+    let prompter = passphrase::Prompter::detect(args.sheet.passphrase.pinentry.clone());
+    let everyday_passphrase = passphrase_or_exit(passphrase::get_passphrase(
+        get_passphrase_source(&args.sheet.passphrase).as_ref(),
+        &prompter,
+    ))?;
+    check_passphrase(&everyday_passphrase, &args.sheet);
 
-    info!("Plaintext length: {plaintext_len:?} bytes");
-    info!("Encrypted length: {:?} bytes", encrypted.len());
+    let break_glass_source = args
+        .break_glass_passphrase_file
+        .clone()
+        .map(passphrase::PassphraseSource::File);
+    let break_glass_passphrase = passphrase_or_exit(passphrase::get_break_glass_passphrase(
+        break_glass_source.as_ref(),
+        &prompter,
+    ))?;
+    check_passphrase(&break_glass_passphrase, &args.sheet);
 
-    let pdf = builder::Document::new(args.title.clone(), args.page_size)?;
+    if break_glass_passphrase.expose_secret() == everyday_passphrase.expose_secret() {
+        error!("The break-glass tier needs a different passphrase than the everyday tier");
+        std::process::exit(exitcode::DATAERR);
+    }
 
-    if args.grid {
+    let (work_factor, work_factor_details) = get_work_factor(&args.sheet);
+    let mut details = vec![work_factor_details];
+    details.extend(qr_encoding_details(args.sheet.qr_encoding));
+
+    let tiers = [
+        (
+            builder::Slot::Left,
+            &args.everyday_label,
+            everyday,
+            everyday_passphrase,
+        ),
+        (
+            builder::Slot::Right,
+            &args.break_glass_label,
+            break_glass,
+            break_glass_passphrase,
+        ),
+    ];
+
+    let pdf = builder::Document::new(args.sheet.title.clone(), args.sheet.page_size.clone())?;
+    if args.sheet.grid {
         pdf.draw_grid();
     }
+    pdf.insert_title_text(args.sheet.title.clone());
 
-    pdf.insert_title_text(args.title);
+    let mut ciphertexts = vec![];
+    for (slot, label, (plaintext, public_keys), passphrase) in tiers {
+        let (plaintext, plaintext_details) = prepare_plaintext(plaintext, args.compress);
+        details.extend(
+            plaintext_details
+                .into_iter()
+                .map(|line| format!("{label}: {line}")),
+        );
+        details.extend(
+            public_keys
+                .iter()
+                .map(|key| format!("{label} public key: {key}")),
+        );
 
-    match pdf.insert_qr_code(encrypted.clone()) {
-        Ok(()) => (),
-        Err(error) => {
-            if error.is::<QrError>() {
-                error!("Too much data after encryption, please try a smaller file");
-                std::process::exit(exitcode::DATAERR);
-            } else {
-                error!("The QR code generation failed for an unknown reason");
-                std::process::exit(exitcode::SOFTWARE);
+        details.push(format!(
+            "{label}: {}",
+            passphrase::charset_hint(&passphrase)
+        ));
+
+        let (plaintext_len, encrypted) =
+            encryption::encrypt_plaintext(&mut &plaintext[..], passphrase, work_factor)?;
+        info!("{label} plaintext length: {plaintext_len:?} bytes");
+        info!("{label} encrypted length: {:?} bytes", encrypted.len());
+
+        let fingerprint = fingerprint::Fingerprint::of_ciphertext(&encrypted);
+        info!("{label} ciphertext fingerprint: {fingerprint}");
+        details.push(format!("{label} fingerprint: {fingerprint}"));
+        pdf.insert_tier_fingerprint(&fingerprint, slot);
+
+        let qr_payload = payload::encode(&encrypted, args.sheet.qr_encoding)?;
+        info!(
+            "{label} QR code payload: {} bytes ({})",
+            qr_payload.len(),
+            args.sheet.qr_encoding
+        );
+        qr_code_or_exit(pdf.insert_qr_code(qr_payload, slot));
+
+        pdf.insert_tier_label(label.clone(), slot);
+        pdf.insert_notes_field(
+            args.sheet.notes_label.clone(),
+            args.sheet.skip_notes_line,
+            slot,
+        );
+
+        ciphertexts.push((slot, encrypted));
+    }
+
+    pdf.insert_details(details);
+    pdf.insert_tier_dividers();
+
+    let armored: Vec<String> = ciphertexts
+        .iter()
+        .map(|(_, encrypted)| encrypted.clone())
+        .collect();
+    for (slot, encrypted) in ciphertexts {
+        layout_or_exit(pdf.insert_pem_text(encrypted, slot));
+    }
+
+    pdf.insert_footer();
+
+    save_pdf(pdf, &args.sheet.output)?;
+    if let Some(path) = &args.sheet.armor_output {
+        save_armor(&armored, path)?;
+    }
+
+    Ok(())
+}
+
+/// Line for the details about the QR code payload, unless it's ASCII armored
+fn qr_encoding_details(encoding: payload::QrEncoding) -> Option<String> {
+    match encoding {
+        payload::QrEncoding::Armor => None,
+        payload::QrEncoding::Binary => Some(String::from(
+            "QR code: age binary format (not ASCII armored)",
+        )),
+        payload::QrEncoding::Base45 => Some(String::from(
+            "QR code: base45 (RFC 9285) encoded age binary format (not ASCII armored)",
+        )),
+    }
+}
+
+/// Check that the QR code was inserted, or log the error and exit
+fn qr_code_or_exit(result: Result<(), Box<dyn std::error::Error>>) {
+    if let Err(error) = result {
+        if error.is::<QrError>() {
+            error!("Too much data after encryption, please try a smaller file");
+            std::process::exit(exitcode::DATAERR);
+        } else {
+            error!("The QR code generation failed for an unknown reason");
+            std::process::exit(exitcode::SOFTWARE);
+        }
+    }
+}
+
+/// Check that the content fit on the sheet, or log the error and exit
+fn layout_or_exit(result: Result<(), Box<dyn std::error::Error>>) {
+    if let Err(error) = result {
+        error!("{error}");
+        error!("Try a smaller input, fewer notes, or --key-sheet");
+        std::process::exit(exitcode::DATAERR);
+    }
+}
+
+/// Shorten a public key to its first 11 and last 4 characters (e.g.
+/// age1g3jnt5c...r8sy)
+fn abbreviate_public_key(public_key: &str) -> String {
+    let chars: Vec<char> = public_key.chars().collect();
+    if chars.len() <= 16 {
+        return public_key.to_string();
+    }
+
+    format!(
+        "{}...{}",
+        chars[..11].iter().collect::<String>(),
+        chars[chars.len() - 4..].iter().collect::<String>()
+    )
+}
+
+/// Sheet for one of the holders of the same secret, with the holder's name and
+/// the set ID in the side note
+fn holder_sheet(plaintext: &[u8], holder: &str, set_id: &str, total: usize) -> Sheet {
+    Sheet {
+        plaintext: secret_or_exit(memory::SecretBytes::from_vec(plaintext.to_vec())),
+        side_note: vec![
+            String::from("Holder:"),
+            holder.to_string(),
+            format!("Set ID: {set_id}"),
+        ],
+        details: vec![format!(
+            "Sheet of {holder}: the same secret is on {total} sheets of set {set_id}, each with its holder's passphrase"
+        )],
+    }
+}
+
+/// Ask each holder for their passphrase in turn. The entries have to be
+/// confirmed and pass the strength check.
+fn holder_passphrases(
+    args: &cli::SheetArgs,
+) -> Result<Vec<SecretString>, Box<dyn std::error::Error>> {
+    if env::var_os("PAPERAGE_PASSPHRASE").is_some() {
+        warn!("Ignoring PAPERAGE_PASSPHRASE, each holder enters their own passphrase");
+    }
+
+    let prompter = passphrase::Prompter::detect(args.passphrase.pinentry.clone());
+    let mut passphrases: Vec<SecretString> = vec![];
+
+    for holder in &args.holders {
+        let passphrase = passphrase_or_exit(passphrase::get_holder_passphrase(holder, &prompter))?;
+        check_passphrase(&passphrase, args);
+
+        for (other, other_passphrase) in args.holders.iter().zip(&passphrases) {
+            if other_passphrase.expose_secret() == passphrase.expose_secret() {
+                warn!("{other} and {holder} have the same passphrase");
             }
         }
+
+        passphrases.push(passphrase);
+    }
+
+    Ok(passphrases)
+}
+
+/// Warn about characters that are hard to type, and check the strength of the
+/// passphrase unless weak passphrases are allowed, or log the error and exit
+fn check_passphrase(passphrase: &SecretString, args: &cli::SheetArgs) {
+    for warning in passphrase::typing_warnings(passphrase) {
+        warn!("{warning}");
+    }
+
+    if args.allow_weak_passphrase {
+        debug!("Skipping the passphrase strength check");
+    } else if let Err(e) = passphrase::check_strength(passphrase, args.min_passphrase_score) {
+        error!("{e}");
+        error!("Use --allow-weak-passphrase to use it anyway");
+        std::process::exit(exitcode::DATAERR);
+    }
+}
+
+/// Unwrap the passphrase, or log the error and exit with a matching exit code
+fn passphrase_or_exit(
+    result: Result<SecretString, io::Error>,
+) -> Result<SecretString, Box<dyn std::error::Error>> {
+    match result {
+        Ok(passphrase) => Ok(passphrase),
+        Err(e) => {
+            error!("{e}");
+            match e.kind() {
+                io::ErrorKind::InvalidInput => std::process::exit(exitcode::DATAERR),
+                io::ErrorKind::NotFound => std::process::exit(exitcode::NOINPUT),
+                io::ErrorKind::PermissionDenied => std::process::exit(exitcode::NOPERM),
+                _ => Err(e.into()),
+            }
+        }
+    }
+}
+
+/// Sheet for one Shamir share, with the share details in the side note
+fn share_sheet(share: &shamir::Share, custodian: Option<&String>) -> Sheet {
+    let mut side_note = vec![
+        format!("Share {} of {}", share.index, share.total),
+        format!("Threshold: {}", share.threshold),
+        format!("Set ID: {}", share.set_id_hex()),
+    ];
+    if let Some(custodian) = custodian {
+        side_note.push(String::from("Custodian:"));
+        side_note.push(custodian.clone());
+    }
+
+    Sheet {
+        plaintext: secret_or_exit(memory::SecretBytes::from_vec(share.to_bytes())),
+        side_note,
+        details: vec![format!(
+            "Shamir share {} of {}: combine any {} decrypted shares of set {} with paper-age combine",
+            share.index,
+            share.total,
+            share.threshold,
+            share.set_id_hex()
+        )],
+    }
+}
+
+/// Lay out one sheet (title, QR code, notes, ciphertext, and small print) on
+/// the current page
+fn insert_sheet(
+    pdf: &builder::Document,
+    args: &cli::SheetArgs,
+    encrypted: String,
+    fingerprints: &[String],
+    side_note: Vec<String>,
+    mut details: Vec<String>,
+    signature: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if args.grid {
+        pdf.draw_grid();
+    }
+
+    pdf.insert_title_text(args.title.clone());
+
+    let fingerprint = fingerprint::Fingerprint::of_ciphertext(&encrypted);
+    info!("Ciphertext fingerprint: {fingerprint}");
+    pdf.insert_fingerprint(&fingerprint);
+
+    let qr_payload = payload::encode(&encrypted, args.qr_encoding)?;
+    info!(
+        "QR code payload: {} bytes ({})",
+        qr_payload.len(),
+        args.qr_encoding
+    );
+    details.extend(qr_encoding_details(args.qr_encoding));
+
+    qr_code_or_exit(pdf.insert_qr_code(qr_payload, builder::Slot::Full));
+
+    if !side_note.is_empty() {
+        pdf.insert_side_note(side_note);
+    }
+
+    if let Some(signature) = signature {
+        layout_or_exit(pdf.insert_signature_qr_code(signature));
+        details.push(format!(
+            "SSH signature of the armored ciphertext (ssh-keygen -Y verify -n {}):",
+            signature::NAMESPACE
+        ));
+        details.extend(signature.lines().map(String::from));
     }
 
-    pdf.insert_notes_field(args.notes_label, args.skip_notes_line);
+    if fingerprints.is_empty() {
+        pdf.insert_notes_field(
+            args.notes_label.clone(),
+            args.skip_notes_line,
+            builder::Slot::Full,
+        );
+    } else {
+        pdf.insert_recipients_field(fingerprints.to_vec());
+    }
 
     pdf.draw_line(
         vec![
@@ -121,69 +1234,536 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         },
     );
 
-    pdf.insert_pem_text(encrypted);
+    if !details.is_empty() {
+        pdf.insert_details(details);
+    }
+
+    layout_or_exit(pdf.insert_pem_text(encrypted, builder::Slot::Full));
 
     pdf.insert_footer();
 
-    if output == PathBuf::from("-") {
+    Ok(())
+}
+
+/// Decrypt a sheet from typed, pasted, or scanned text
+fn decrypt(args: &cli::DecryptArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let passphrase_source = get_passphrase_source(&args.passphrase);
+
+    #[cfg(unix)]
+    if passphrase_source == Some(passphrase::PassphraseSource::Fd(0)) && args.input.is_none() {
+        error!("Can't read both the passphrase and the input from STDIN");
+        std::process::exit(exitcode::USAGE);
+    }
+
+    let (input, ciphertext) = read_sheet(args.input.as_deref())?;
+
+    if let Some(public_key) = &args.verify_with {
+        // Same as the printed armor, however the ciphertext was typed
+        let armored = payload::armor(&ciphertext)?;
+        verify_signature(public_key, args.signature.as_deref(), &input, &armored);
+    }
+
+    let prompter = passphrase::Prompter::detect(args.passphrase.pinentry.clone());
+    let (plaintext, inner_layer) = open_sheet(&ciphertext, &args.identities, "--identity", || {
+        passphrase::get_decryption_passphrase(passphrase_source.as_ref(), &prompter)
+    })?;
+
+    if inner_layer {
+        warn!("The sheet has a second factor, use --identity to decrypt the inner layer too");
+        return write_secret(&args.output, &plaintext, args.force);
+    }
+
+    if shamir::is_share(&plaintext) {
+        warn!("This sheet is a Shamir share, use paper-age combine to recover the secret");
+        return write_secret(&args.output, &plaintext, args.force);
+    }
+
+    let plaintext = expand_plaintext(&plaintext);
+    if openpgp::is_minimized(&plaintext) {
+        warn!("This sheet only has OpenPGP secret key material, use paper-age restore-openpgp to rebuild the secret key");
+    }
+
+    write_secret(&args.output, &plaintext, args.force)
+}
+
+/// Re-encrypt an existing sheet with a new passphrase or recipients, without
+/// writing the plaintext anywhere
+fn rewrap(args: cli::RewrapArgs) -> Result<(), Box<dyn std::error::Error>> {
+    check_sheet_args(&args.sheet);
+    #[cfg(unix)]
+    check_stdin(&args.sheet.passphrase, args.input.as_deref());
+
+    let (_, ciphertext) = read_sheet(args.input.as_deref())?;
+
+    let old_source = args
+        .old_passphrase_file
+        .clone()
+        .map(passphrase::PassphraseSource::File);
+    let prompter = passphrase::Prompter::detect(args.sheet.passphrase.pinentry.clone());
+    let (plaintext, inner_layer) =
+        open_sheet(&ciphertext, &args.old_identities, "--old-identity", || {
+            passphrase::get_old_passphrase(old_source.as_ref(), &prompter)
+        })?;
+
+    let plaintext = if inner_layer {
+        warn!("The old sheet has a second factor, keeping its inner layer as it is");
+        plaintext
+    } else if shamir::is_share(&plaintext) {
+        if args.sheet.shares.is_some() {
+            error!("The old sheet is a Shamir share, it can't be split into shares again");
+            std::process::exit(exitcode::USAGE);
+        }
+        info!("The old sheet is a Shamir share, rewrapping it as a share");
+        plaintext
+    } else {
+        secret_or_exit(compression::decompress(&plaintext))
+    };
+
+    info!("Decrypted the old sheet, writing the new one");
+
+    let plaintext = check_mnemonic(plaintext, args.bip39_entropy, args.allow_invalid_mnemonic);
+    let (plaintext, public_keys) = compact_identity_file(plaintext);
+
+    let encryption = prepare_encryption(&args.sheet)?;
+    write_sheets(
+        args.sheet,
+        args.compress,
+        encryption,
+        Sheet::new(plaintext),
+        &public_keys,
+    )
+}
+
+/// Generate an age identity and lay it out on a sheet, with the public key in
+/// the clear. The public key is also written to STDOUT.
+fn keygen(args: cli::KeygenArgs) -> Result<(), Box<dyn std::error::Error>> {
+    if args.sheet.output.as_os_str() == "-" {
+        error!("keygen writes the public key to STDOUT, use --output for the PDF");
+        std::process::exit(exitcode::USAGE);
+    }
+
+    check_sheet_args(&args.sheet);
+
+    let (identity_file, public_key) = secret_or_exit(keygen::generate());
+    info!("Generated an X25519 identity: {public_key}");
+
+    let encryption = prepare_encryption(&args.sheet)?;
+    write_sheets(
+        args.sheet,
+        false,
+        encryption,
+        Sheet::new(identity_file),
+        std::slice::from_ref(&public_key),
+    )?;
+
+    println!("{public_key}");
+
+    Ok(())
+}
+
+/// Read the text of a sheet from a file or STDIN and convert the ciphertext
+/// to the age binary format. Returns both the input and the ciphertext.
+fn read_sheet(path: Option<&Path>) -> Result<(Vec<u8>, Vec<u8>), Box<dyn std::error::Error>> {
+    let mut input = vec![];
+    match path {
+        None => {
+            stdin().lock().read_to_end(&mut input)?;
+        }
+        Some(path) if path.as_os_str() == "-" => {
+            stdin().lock().read_to_end(&mut input)?;
+        }
+        Some(path) if path.is_file() => {
+            File::open(path)?.read_to_end(&mut input)?;
+        }
+        Some(path) => {
+            error!("File not found: {}", path.display());
+            std::process::exit(exitcode::NOINPUT);
+        }
+    }
+
+    let ciphertext = match decryption::read_ciphertext(&input) {
+        Ok(ciphertext) => ciphertext,
+        Err(e) => {
+            error!("{e}");
+            std::process::exit(exitcode::DATAERR);
+        }
+    };
+
+    // Same as the fingerprint on the sheet, however the ciphertext was typed
+    let fingerprint = fingerprint::Fingerprint::of_ciphertext(&payload::armor(&ciphertext)?);
+    info!("Ciphertext fingerprint: {fingerprint}");
+
+    Ok((input, ciphertext))
+}
+
+/// Decrypt the ciphertext of a sheet with a passphrase or with the identities
+/// in the given files, or log the error and exit. Also returns whether the
+/// inner layer of a two factor sheet is still encrypted, because there were no
+/// identities for it.
+fn open_sheet(
+    ciphertext: &[u8],
+    identity_paths: &[PathBuf],
+    identity_option: &str,
+    get_passphrase: impl FnOnce() -> Result<SecretString, io::Error>,
+) -> Result<(memory::SecretBytes, bool), Box<dyn std::error::Error>> {
+    let passphrase_encrypted = match decryption::is_passphrase_encrypted(ciphertext) {
+        Ok(passphrase_encrypted) => passphrase_encrypted,
+        Err(e) => exit_with_decrypt_error(e),
+    };
+
+    // Needed for sheets encrypted to recipients, and for the inner layer of
+    // two factor sheets
+    let mut identities = vec![];
+    for path in identity_paths {
+        match decryption::read_identities(path) {
+            Ok(mut file_identities) => identities.append(&mut file_identities),
+            Err(e) => {
+                error!("Could not read the identities from {}: {e}", path.display());
+                std::process::exit(exitcode::NOINPUT);
+            }
+        }
+    }
+
+    let result = if passphrase_encrypted {
+        let passphrase = passphrase_or_exit(get_passphrase())?;
+
+        decryption::decrypt_with_passphrase(ciphertext, passphrase)
+    } else {
+        if identities.is_empty() {
+            error!("The sheet is encrypted to recipients, use {identity_option} to decrypt it");
+            std::process::exit(exitcode::USAGE);
+        }
+
+        decryption::decrypt(ciphertext, &identities)
+    };
+
+    let plaintext = match result {
+        Ok(plaintext) => plaintext,
+        Err(e) => exit_with_decrypt_error(e),
+    };
+
+    // Two factor sheets have an age ciphertext encrypted to recipients inside
+    // the passphrase layer
+    if passphrase_encrypted && decryption::is_age_binary(&plaintext) {
+        if identities.is_empty() {
+            return Ok((plaintext, true));
+        }
+
+        debug!("Decrypting the inner layer with the identities");
+        return match decryption::decrypt(&plaintext, &identities) {
+            Ok(plaintext) => Ok((plaintext, false)),
+            Err(e) => exit_with_decrypt_error(e),
+        };
+    }
+
+    if passphrase_encrypted && !identities.is_empty() {
+        warn!("The sheet is only encrypted with a passphrase, ignoring the identities");
+    }
+
+    Ok((plaintext, false))
+}
+
+/// Verify the signature of the sheet from the signature file or the input, or
+/// log the error and exit
+fn verify_signature(public_key: &Path, signature_path: Option<&Path>, input: &[u8], armored: &str) {
+    let public_key = match signature::read_public_key(public_key) {
+        Ok(public_key) => public_key,
+        Err(e) => {
+            error!("{e}");
+            std::process::exit(exitcode::NOINPUT);
+        }
+    };
+
+    let signature_text = match signature_path {
+        Some(path) => match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) => {
+                error!("Could not read the signature from {}: {e}", path.display());
+                std::process::exit(exitcode::NOINPUT);
+            }
+        },
+        None => String::from_utf8_lossy(input).into_owned(),
+    };
+
+    let result = match signature::find_signature(&signature_text) {
+        Ok(Some(sig)) => signature::verify(&public_key, armored, &sig),
+        Ok(None) => {
+            error!("No signature found, use --signature to read it from a separate file");
+            std::process::exit(exitcode::DATAERR);
+        }
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(()) => info!(
+            "Good signature from {}",
+            public_key.fingerprint(ssh_key::HashAlg::Sha256)
+        ),
+        Err(e) => {
+            error!("{e}");
+            error!("Refusing to decrypt a sheet with an invalid signature");
+            std::process::exit(exitcode::DATAERR);
+        }
+    }
+}
+
+/// Log the decryption error and exit with a matching exit code
+fn exit_with_decrypt_error(error: age::DecryptError) -> ! {
+    match error {
+        age::DecryptError::DecryptionFailed
+        | age::DecryptError::KeyDecryptionFailed
+        | age::DecryptError::NoMatchingKeys => {
+            error!("Decryption failed, check the passphrase or the identities");
+            std::process::exit(exitcode::NOPERM);
+        }
+        age::DecryptError::MissingPlugin { binary_name } => {
+            error!("Could not find '{binary_name}' in PATH");
+            std::process::exit(exitcode::UNAVAILABLE);
+        }
+        age::DecryptError::Plugin(errors) => {
+            for e in errors {
+                error!("{e}");
+            }
+            std::process::exit(exitcode::PROTOCOL);
+        }
+        age::DecryptError::Io(e) => secret_or_exit(Err(e)),
+        e => {
+            error!("{e}");
+            std::process::exit(exitcode::DATAERR);
+        }
+    }
+}
+
+/// Recover a secret from decrypted Shamir shares
+fn combine(args: &cli::CombineArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let mut shares = vec![];
+
+    for path in &args.shares {
+        let bytes = match fs::read(path) {
+            Ok(bytes) => Zeroizing::new(bytes),
+            Err(e) => {
+                error!("Could not read {}: {e}", path.display());
+                std::process::exit(exitcode::NOINPUT);
+            }
+        };
+
+        match shamir::Share::from_bytes(&bytes) {
+            Ok(share) => {
+                debug!(
+                    "Share {} of {} from set {}",
+                    share.index,
+                    share.total,
+                    share.set_id_hex()
+                );
+                shares.push(share);
+            }
+            Err(e) => {
+                error!("{}: {e}", path.display());
+                std::process::exit(exitcode::DATAERR);
+            }
+        }
+    }
+
+    let secret = match shamir::combine(&shares) {
+        Ok(secret) => secret_or_exit(memory::SecretBytes::from_vec(secret)),
+        Err(e) => {
+            error!("{e}");
+            std::process::exit(exitcode::DATAERR);
+        }
+    };
+
+    write_secret(&args.output, &expand_plaintext(&secret), args.force)
+}
+
+/// Decompress the plaintext and turn a compact BIP39 mnemonic back into the
+/// words, or log the error and exit
+fn expand_plaintext(plaintext: &[u8]) -> memory::SecretBytes {
+    let plaintext = secret_or_exit(compression::decompress(plaintext));
+    if !bip39::is_compact(&plaintext) {
+        return plaintext;
+    }
+
+    match bip39::expand(&plaintext) {
+        Ok(mnemonic) => {
+            info!("Turned the BIP39 entropy back into the mnemonic");
+            mnemonic
+        }
+        Err(e) if memory::is_lock_error(&e) => secret_or_exit(Err(e)),
+        Err(e) => {
+            error!("{e}");
+            std::process::exit(exitcode::DATAERR);
+        }
+    }
+}
+
+/// Rebuild an OpenPGP secret key from the decrypted secret key material and the
+/// public key
+fn restore_openpgp(args: &cli::RestoreOpenpgpArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let minimized = match args.input.as_deref() {
+        None => secret_or_exit(memory::SecretBytes::read_from(&mut stdin().lock())),
+        Some(path) if path.as_os_str() == "-" => {
+            secret_or_exit(memory::SecretBytes::read_from(&mut stdin().lock()))
+        }
+        Some(path) => match File::open(path) {
+            Ok(mut file) => secret_or_exit(memory::SecretBytes::read_from(&mut file)),
+            Err(e) => {
+                error!("Could not read {}: {e}", path.display());
+                std::process::exit(exitcode::NOINPUT);
+            }
+        },
+    };
+
+    let public_key = match fs::read(&args.public_key) {
+        Ok(public_key) => public_key,
+        Err(e) => {
+            error!("Could not read {}: {e}", args.public_key.display());
+            std::process::exit(exitcode::NOINPUT);
+        }
+    };
+
+    let restored = match openpgp::restore(&compression::decompress(&minimized)?, &public_key) {
+        Ok(restored) => restored,
+        Err(e) if memory::is_lock_error(&e) => secret_or_exit(Err(e)),
+        Err(e) => {
+            error!("{e}");
+            std::process::exit(exitcode::DATAERR);
+        }
+    };
+    info!("Restored the OpenPGP secret key ({} bytes)", restored.len());
+
+    write_secret(&args.output, &restored, args.force)
+}
+
+/// Unwrap the result of reading or protecting a secret, or log the error and
+/// exit. Memory locking failures in paranoid mode are system errors.
+fn secret_or_exit<T>(result: Result<T, io::Error>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(e) if memory::is_lock_error(&e) => {
+            error!("{e}");
+            error!("Refusing to continue in paranoid mode");
+            std::process::exit(exitcode::OSERR);
+        }
+        Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+            error!("{e}");
+            std::process::exit(exitcode::DATAERR);
+        }
+        Err(e) => {
+            error!("{e}");
+            std::process::exit(exitcode::IOERR);
+        }
+    }
+}
+
+/// Write a recovered secret to STDOUT or to a new file that only the current
+/// user can read
+fn write_secret(path: &Path, data: &[u8], force: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if path.as_os_str() == "-" {
         debug!("Writing to STDOUT");
-        let bytes = pdf.doc.save_to_bytes()?;
-        io::stdout().write_all(&bytes)?;
+        io::stdout().write_all(data)?;
+        return Ok(());
+    }
+
+    debug!("Writing to file: {}", path.display());
+
+    let mut options = OpenOptions::new();
+    options.write(true);
+    if force {
+        options.create(true).truncate(true);
     } else {
-        debug!("Writing to file: {}", output.to_string_lossy());
-        let file = File::create(output)?;
-        pdf.doc.save(&mut BufWriter::new(file))?;
+        options.create_new(true);
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    match options.open(path) {
+        Ok(mut file) => file.write_all(data)?,
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+            error!("Output file already exists: {}", path.display());
+            std::process::exit(exitcode::CANTCREAT);
+        }
+        Err(e) => return Err(e.into()),
     }
 
     Ok(())
 }
 
-/// Read a secret from the user
-pub fn read_secret(prompt: &str) -> Result<Secret<String>, io::Error> {
-    let passphrase = prompt_password(format!("{}: ", prompt)).map(SecretString::new)?;
+/// Log the error from reading the recipients and exit with a matching exit
+/// code
+fn exit_with_recipients_error(error: io::Error) -> ! {
+    error!("{error}");
+    if error.kind() == io::ErrorKind::NotFound {
+        std::process::exit(exitcode::UNAVAILABLE);
+    } else {
+        std::process::exit(exitcode::DATAERR);
+    }
+}
 
-    if passphrase.expose_secret().is_empty() {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidInput,
-            "Passphrase can't be empty",
-        ));
+/// Format a duration in a human friendly way (e.g. 3 s or 2 min)
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs_f64();
+
+    if seconds < 1.0 {
+        String::from("<1 s")
+    } else if seconds < 120.0 {
+        format!("{seconds:.0} s")
+    } else if seconds < 7200.0 {
+        format!("{:.0} min", seconds / 60.0)
+    } else {
+        format!("{:.0} h", seconds / 3600.0)
+    }
+}
+
+/// Non-interactive passphrase source from the command line arguments, if any
+fn get_passphrase_source(args: &cli::PassphraseArgs) -> Option<passphrase::PassphraseSource> {
+    #[cfg(unix)]
+    if let Some(fd) = args.fd {
+        if fd == 1 || fd == 2 {
+            error!("Can't read the passphrase from STDOUT or STDERR (file descriptor {fd})");
+            std::process::exit(exitcode::USAGE);
+        }
+        return Some(passphrase::PassphraseSource::Fd(fd));
     }
 
-    Ok(passphrase)
+    args.file.clone().map(passphrase::PassphraseSource::File)
 }
 
-/// Get the passphrase from an interactive prompt or from the PAPERAGE_PASSPHRASE
-/// environment variable
-fn get_passphrase() -> Result<Secret<String>, io::Error> {
-    let env_passphrase = env::var("PAPERAGE_PASSPHRASE");
+/// Collect the recipients given on the command line, in recipients files, and
+/// optionally the SSH public keys from ~/.ssh
+fn get_recipients(
+    values: &[String],
+    files: &[PathBuf],
+    ssh_keys: bool,
+) -> Result<Vec<recipients::Recipient>, io::Error> {
+    let mut result = vec![];
 
-    if let Ok(value) = env_passphrase {
-        return Ok(SecretString::from(value));
+    for value in values {
+        result.push(recipients::parse_recipient(value)?);
     }
 
-    match read_secret("Passphrase") {
-        Ok(secret) => Ok(secret),
-        Err(e) => Err(io::Error::new(io::ErrorKind::Other, format!("{e}"))),
+    for path in files {
+        result.append(&mut recipients::read_recipients_file(path)?);
     }
+
+    if ssh_keys {
+        result.append(&mut recipients::read_ssh_public_keys()?);
+    }
+
+    Ok(result)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use age::secrecy::ExposeSecret;
 
     #[test]
-    fn test_get_passphrase_from_env() -> Result<(), Box<dyn std::error::Error>> {
-        env::set_var("PAPERAGE_PASSPHRASE", "secret");
-
-        let result = get_passphrase();
-        assert!(result.is_ok());
-
-        let passphrase = result?;
-        passphrase.expose_secret();
-
-        assert_eq!(passphrase.expose_secret(), "secret");
-
-        Ok(())
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_millis(300)), "<1 s");
+        assert_eq!(format_duration(Duration::from_secs(10)), "10 s");
+        assert_eq!(format_duration(Duration::from_secs(600)), "10 min");
+        assert_eq!(format_duration(Duration::from_secs(36000)), "10 h");
     }
 }
@@ -0,0 +1,277 @@
+//! Protection of secrets in memory: zeroized and locked buffers, and no core
+//! dumps
+use std::{
+    error::Error,
+    fmt,
+    io::{self, Read, Write},
+    ops::Deref,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use zeroize::Zeroize;
+
+/// Initial capacity of a secret buffer that is read from a reader
+const INITIAL_CAPACITY: usize = 4096;
+
+/// Refuse to continue if memory can't be locked
+static PARANOID: AtomicBool = AtomicBool::new(false);
+
+/// Make memory locking failures fatal instead of just logging them
+pub fn set_paranoid(paranoid: bool) {
+    PARANOID.store(paranoid, Ordering::Relaxed);
+}
+
+/// Disable core dumps for this process, so that secrets in memory don't end up
+/// on disk if it crashes
+#[cfg(unix)]
+pub fn disable_core_dumps() -> Result<(), io::Error> {
+    let limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+
+    // SAFETY: setrlimit only reads the given struct
+    if unsafe { libc::setrlimit(libc::RLIMIT_CORE, &limit) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // Also prevents ptrace attaching and reading /proc/<pid>/mem as the same
+    // user (without CAP_SYS_PTRACE)
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    // SAFETY: PR_SET_DUMPABLE takes a single integer argument
+    if unsafe { libc::prctl(libc::PR_SET_DUMPABLE, 0, 0, 0, 0) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    debug!("Disabled core dumps");
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn disable_core_dumps() -> Result<(), io::Error> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Disabling core dumps isn't supported on this platform",
+    ))
+}
+
+/// Memory couldn't be locked in paranoid mode
+#[derive(Debug)]
+pub struct LockError(io::Error);
+
+impl fmt::Display for LockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Could not lock memory: {}", self.0)
+    }
+}
+
+impl Error for LockError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+/// Check if the error is a memory locking failure in paranoid mode
+pub fn is_lock_error(error: &io::Error) -> bool {
+    error.get_ref().is_some_and(|e| e.is::<LockError>())
+}
+
+/// A byte buffer for plaintexts that is locked in memory (where possible) and
+/// zeroized when it's dropped or grown
+pub struct SecretBytes {
+    buffer: Vec<u8>,
+    locked: bool,
+}
+
+impl SecretBytes {
+    /// Empty buffer with the given capacity
+    pub fn with_capacity(capacity: usize) -> Result<SecretBytes, io::Error> {
+        let buffer = Vec::with_capacity(capacity);
+        let locked = lock(&buffer)?;
+
+        Ok(SecretBytes { buffer, locked })
+    }
+
+    /// Move the data into a secret buffer. The original vector is zeroized.
+    pub fn from_vec(mut data: Vec<u8>) -> Result<SecretBytes, io::Error> {
+        let secret = SecretBytes::with_capacity(data.len()).map(|mut secret| {
+            secret.buffer.extend_from_slice(&data);
+            secret
+        });
+        data.zeroize();
+
+        secret
+    }
+
+    /// Read everything from the reader into a secret buffer, without leaving
+    /// copies behind when the buffer grows
+    pub fn read_from(reader: &mut (impl Read + ?Sized)) -> Result<SecretBytes, io::Error> {
+        let mut secret = SecretBytes::with_capacity(INITIAL_CAPACITY)?;
+
+        loop {
+            if secret.buffer.len() == secret.buffer.capacity() {
+                secret.reserve(secret.buffer.capacity())?;
+            }
+
+            // Read directly into the spare capacity
+            let len = secret.buffer.len();
+            let capacity = secret.buffer.capacity();
+            secret.buffer.resize(capacity, 0);
+
+            match reader.read(&mut secret.buffer[len..]) {
+                Ok(0) => {
+                    secret.buffer.truncate(len);
+                    return Ok(secret);
+                }
+                Ok(n) => secret.buffer.truncate(len + n),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => secret.buffer.truncate(len),
+                Err(e) => {
+                    secret.buffer.truncate(len);
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    /// Whether the buffer is locked in memory
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    /// Make room for at least the given number of additional bytes. The
+    /// contents are moved to a new buffer and the old one is zeroized.
+    fn reserve(&mut self, additional: usize) -> Result<(), io::Error> {
+        let required = self.buffer.len() + additional;
+        if required <= self.buffer.capacity() {
+            return Ok(());
+        }
+
+        let mut grown = SecretBytes::with_capacity(required.max(self.buffer.capacity() * 2))?;
+        grown.buffer.extend_from_slice(&self.buffer);
+
+        // The old buffer is zeroized and unlocked when it's dropped
+        std::mem::swap(self, &mut grown);
+
+        Ok(())
+    }
+}
+
+impl Deref for SecretBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.buffer
+    }
+}
+
+impl Write for SecretBytes {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.reserve(data.len())?;
+        self.buffer.extend_from_slice(data);
+
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for SecretBytes {
+    fn drop(&mut self) {
+        // Zeroizes the whole capacity, not just the length
+        self.buffer.zeroize();
+
+        if self.locked {
+            unlock(&self.buffer);
+        }
+    }
+}
+
+/// Lock the allocation of the buffer in memory so that it isn't swapped out.
+/// Returns false if the memory couldn't be locked (and paranoid mode is off).
+#[cfg(unix)]
+fn lock(buffer: &Vec<u8>) -> Result<bool, io::Error> {
+    if buffer.capacity() == 0 {
+        return Ok(true);
+    }
+
+    // SAFETY: the pointer and the capacity describe a single live allocation
+    if unsafe { libc::mlock(buffer.as_ptr().cast(), buffer.capacity()) } == 0 {
+        trace!("Locked {} bytes of memory", buffer.capacity());
+        return Ok(true);
+    }
+
+    lock_failed(io::Error::last_os_error())
+}
+
+#[cfg(not(unix))]
+fn lock(buffer: &Vec<u8>) -> Result<bool, io::Error> {
+    if buffer.capacity() == 0 {
+        return Ok(true);
+    }
+
+    lock_failed(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "not supported on this platform",
+    ))
+}
+
+/// Handle a memory locking failure according to the paranoid mode
+fn lock_failed(error: io::Error) -> Result<bool, io::Error> {
+    if PARANOID.load(Ordering::Relaxed) {
+        return Err(io::Error::other(LockError(error)));
+    }
+
+    debug!("Could not lock memory: {error}");
+
+    Ok(false)
+}
+
+/// Unlock the allocation of the buffer. Locks aren't counted, so this can also
+/// unlock a page shared with another locked buffer.
+#[cfg(unix)]
+fn unlock(buffer: &Vec<u8>) {
+    if buffer.capacity() > 0 {
+        // SAFETY: the pointer and the capacity describe a single live allocation
+        unsafe { libc::munlock(buffer.as_ptr().cast(), buffer.capacity()) };
+    }
+}
+
+#[cfg(not(unix))]
+fn unlock(_buffer: &Vec<u8>) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_from() -> Result<(), io::Error> {
+        let data = "secret ".repeat(2000);
+        let secret = SecretBytes::read_from(&mut data.as_bytes())?;
+
+        assert_eq!(&secret[..], data.as_bytes());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_vec() -> Result<(), io::Error> {
+        let secret = SecretBytes::from_vec(b"secret".to_vec())?;
+
+        assert_eq!(&secret[..], b"secret");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write() -> Result<(), io::Error> {
+        let mut secret = SecretBytes::with_capacity(2)?;
+        secret.write_all(b"more than two bytes")?;
+
+        assert_eq!(&secret[..], b"more than two bytes");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,539 @@
+//! Passphrase input and strength checks
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, IsTerminal, Write},
+    path::PathBuf,
+};
+
+use age::secrecy::{ExposeSecret, SecretString};
+use rpassword::prompt_password;
+use unicode_normalization::UnicodeNormalization;
+use zxcvbn::zxcvbn;
+
+use crate::{memory, pinentry};
+
+/// ASCII characters that are dead keys on common European keyboard layouts
+/// (e.g. German, French, Nordic): typing them takes an extra key press, or
+/// they combine with the next letter
+const DEAD_KEYS: [char; 3] = ['`', '^', '~'];
+
+/// Interactive ways of asking the user for a secret
+#[derive(Debug, Clone, PartialEq)]
+pub enum Prompter {
+    /// Prompt on the terminal
+    Terminal,
+
+    /// A GnuPG pinentry program
+    Pinentry(PathBuf),
+
+    /// An SSH_ASKPASS-style helper
+    Askpass(PathBuf),
+}
+
+impl Prompter {
+    /// Pick how to ask for secrets. A graphical helper is picked automatically
+    /// when STDIN isn't a terminal (first SSH_ASKPASS, then a pinentry in
+    /// PATH), or with SSH_ASKPASS_REQUIRE=force (or prefer, with a display).
+    pub fn detect(pinentry: Option<PathBuf>) -> Prompter {
+        if let Some(program) = pinentry {
+            return Prompter::Pinentry(program);
+        }
+
+        let require = env::var("SSH_ASKPASS_REQUIRE").unwrap_or_default();
+        let askpass = env::var_os("SSH_ASKPASS")
+            .filter(|value| !value.is_empty() && require != "never")
+            .map(PathBuf::from);
+
+        if let Some(program) = &askpass {
+            if require == "force" || (require == "prefer" && has_display()) {
+                return Prompter::Askpass(program.clone());
+            }
+        }
+
+        if io::stdin().is_terminal() {
+            return Prompter::Terminal;
+        }
+
+        if let Some(program) = askpass {
+            return Prompter::Askpass(program);
+        }
+
+        match pinentry::find_program("pinentry") {
+            Some(program) => Prompter::Pinentry(program),
+            None => Prompter::Terminal,
+        }
+    }
+
+    /// Read a secret from the user, normalized to NFC
+    pub fn read_secret(&self, prompt: &str) -> Result<SecretString, io::Error> {
+        let passphrase = match self {
+            Prompter::Terminal => {
+                prompt_password(format!("{}: ", prompt)).map(SecretString::from)?
+            }
+            Prompter::Pinentry(program) => pinentry::get_pin(
+                program,
+                "Enter the passphrase for the PaperAge sheet",
+                format!("{prompt}:").as_str(),
+            )?,
+            Prompter::Askpass(program) => {
+                pinentry::askpass(program, format!("{prompt}: ").as_str())?
+            }
+        };
+
+        if passphrase.expose_secret().is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Passphrase can't be empty",
+            ));
+        }
+
+        Ok(normalize(passphrase))
+    }
+
+    /// Read a secret from the user twice and make sure that both entries match
+    pub fn read_confirmed_secret(&self, prompt: &str) -> Result<SecretString, io::Error> {
+        let passphrase = self.read_secret(prompt)?;
+        let confirmation =
+            self.read_secret(format!("Confirm {}", prompt.to_lowercase()).as_str())?;
+
+        if passphrase.expose_secret() != confirmation.expose_secret() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Passphrases don't match",
+            ));
+        }
+
+        Ok(passphrase)
+    }
+}
+
+/// Non-interactive passphrase sources that don't go through the environment
+#[derive(Debug, Clone, PartialEq)]
+pub enum PassphraseSource {
+    /// A file containing the passphrase (e.g. a FIFO or a file only readable
+    /// by the current user)
+    File(PathBuf),
+
+    /// An inherited file descriptor (e.g. `--passphrase-fd 3 3< <(pass show …)`)
+    #[cfg(unix)]
+    Fd(i32),
+}
+
+/// Get the passphrase for a new sheet from the given source, from the
+/// PAPERAGE_PASSPHRASE environment variable, or from an interactive prompt (in
+/// that order). Interactive entries have to be confirmed.
+pub fn get_passphrase(
+    source: Option<&PassphraseSource>,
+    prompter: &Prompter,
+) -> Result<SecretString, io::Error> {
+    match non_interactive_passphrase(source, "PAPERAGE_PASSPHRASE")? {
+        Some(passphrase) => Ok(passphrase),
+        None => prompt_error(prompter.read_confirmed_secret("Passphrase")),
+    }
+}
+
+/// Get the passphrase of the break-glass tier from the given source, from the
+/// PAPERAGE_BREAK_GLASS_PASSPHRASE environment variable, or from an interactive
+/// prompt (in that order). Interactive entries have to be confirmed.
+pub fn get_break_glass_passphrase(
+    source: Option<&PassphraseSource>,
+    prompter: &Prompter,
+) -> Result<SecretString, io::Error> {
+    match non_interactive_passphrase(source, "PAPERAGE_BREAK_GLASS_PASSPHRASE")? {
+        Some(passphrase) => Ok(passphrase),
+        None => prompt_error(prompter.read_confirmed_secret("Break-glass passphrase")),
+    }
+}
+
+/// Get the passphrase of one of the holders of a secret from an interactive
+/// prompt. Entries have to be confirmed.
+pub fn get_holder_passphrase(holder: &str, prompter: &Prompter) -> Result<SecretString, io::Error> {
+    prompt_error(prompter.read_confirmed_secret(&format!("Passphrase for {holder}")))
+}
+
+/// Get the passphrase of an existing sheet the same way as [`get_passphrase`],
+/// without the confirmation
+pub fn get_decryption_passphrase(
+    source: Option<&PassphraseSource>,
+    prompter: &Prompter,
+) -> Result<SecretString, io::Error> {
+    match non_interactive_passphrase(source, "PAPERAGE_PASSPHRASE")? {
+        Some(passphrase) => Ok(passphrase),
+        None => prompt_error(prompter.read_secret("Passphrase")),
+    }
+}
+
+/// Get the passphrase of a sheet that is being rewrapped from the given
+/// source, from the PAPERAGE_OLD_PASSPHRASE environment variable, or from an
+/// interactive prompt
+pub fn get_old_passphrase(
+    source: Option<&PassphraseSource>,
+    prompter: &Prompter,
+) -> Result<SecretString, io::Error> {
+    match non_interactive_passphrase(source, "PAPERAGE_OLD_PASSPHRASE")? {
+        Some(passphrase) => Ok(passphrase),
+        None => prompt_error(prompter.read_secret("Old passphrase")),
+    }
+}
+
+/// Passphrase from the given source or from the environment variable, if any,
+/// normalized to NFC. Stray trailing newlines (\n or \r\n) are removed from the
+/// environment variable.
+fn non_interactive_passphrase(
+    source: Option<&PassphraseSource>,
+    variable: &str,
+) -> Result<Option<SecretString>, io::Error> {
+    if let Some(source) = source {
+        return read_passphrase_source(source).map(|passphrase| Some(normalize(passphrase)));
+    }
+
+    let Ok(value) = env::var(variable).map(SecretString::from) else {
+        return Ok(None);
+    };
+
+    let trimmed = value.expose_secret().trim_end_matches(['\r', '\n']);
+    if trimmed.len() < value.expose_secret().len() {
+        debug!("Removed trailing newlines from {variable}");
+    }
+
+    if trimmed.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Passphrase can't be empty",
+        ));
+    }
+
+    Ok(Some(normalize(SecretString::from(trimmed.to_string()))))
+}
+
+/// Normalize the passphrase to NFC, so that the same passphrase typed on
+/// different systems (e.g. NFD on macOS) is the same bytes
+pub fn normalize(passphrase: SecretString) -> SecretString {
+    let value = passphrase.expose_secret();
+    if value.is_ascii() {
+        return passphrase;
+    }
+
+    let mut normalized = String::with_capacity(value.len() * 2);
+    normalized.extend(value.nfc());
+    if normalized != value {
+        debug!("Normalized the passphrase to NFC");
+    }
+
+    SecretString::from(normalized)
+}
+
+/// Warnings about the characters of the passphrase that are hard to type on
+/// common keyboard layouts. The characters themselves aren't included.
+pub fn typing_warnings(passphrase: &SecretString) -> Vec<String> {
+    let value = passphrase.expose_secret();
+    let mut warnings = vec![];
+
+    if !value.is_ascii() {
+        warnings.push(String::from(
+            "The passphrase contains non-ASCII characters, which are hard to type on keyboard layouts without them",
+        ));
+    }
+
+    if value.chars().any(|c| DEAD_KEYS.contains(&c)) {
+        warnings.push(String::from(
+            "The passphrase contains ` ^ or ~, which are dead keys on many European keyboard layouts",
+        ));
+    }
+
+    if value.chars().any(char::is_control) {
+        warnings.push(String::from(
+            "The passphrase contains a tab or another control character",
+        ));
+    }
+
+    if value.starts_with(' ') || value.ends_with(' ') {
+        warnings.push(String::from(
+            "The passphrase starts or ends with a space, which is easy to miss when typing it",
+        ));
+    }
+
+    warnings
+}
+
+/// Whether there's a display for a graphical helper, as SSH_ASKPASS_REQUIRE
+/// expects
+fn has_display() -> bool {
+    ["DISPLAY", "WAYLAND_DISPLAY"]
+        .iter()
+        .any(|name| env::var_os(name).is_some_and(|value| !value.is_empty()))
+}
+
+/// Hint for the sheet about which characters to expect in the passphrase
+pub fn charset_hint(passphrase: &SecretString) -> String {
+    if passphrase.expose_secret().is_ascii() {
+        String::from("The passphrase is ASCII-only")
+    } else {
+        String::from("The passphrase contains Unicode characters (NFC normalized)")
+    }
+}
+
+/// Keep invalid input errors (e.g. mismatching entries) as they are, wrap
+/// anything else
+fn prompt_error(result: Result<SecretString, io::Error>) -> Result<SecretString, io::Error> {
+    match result {
+        Ok(secret) => Ok(secret),
+        Err(e) if e.kind() == io::ErrorKind::InvalidInput => Err(e),
+        Err(e) => Err(io::Error::other(format!("{e}"))),
+    }
+}
+
+/// Read the passphrase from a file or a file descriptor. A single trailing
+/// newline is removed.
+pub fn read_passphrase_source(source: &PassphraseSource) -> Result<SecretString, io::Error> {
+    let contents = match source {
+        PassphraseSource::File(path) => {
+            debug!("Reading the passphrase from {}", path.display());
+            // Check the file that is actually read, not whatever is at the path
+            let mut file = File::open(path)?;
+            check_permissions(&file.metadata()?, path)?;
+            memory::SecretBytes::read_from(&mut file)?
+        }
+        #[cfg(unix)]
+        PassphraseSource::Fd(fd) => {
+            debug!("Reading the passphrase from file descriptor {fd}");
+            memory::SecretBytes::read_from(&mut inherited_fd(*fd)?)?
+        }
+    };
+    let contents = std::str::from_utf8(&contents)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Passphrase isn't valid UTF-8"))?;
+
+    let trimmed = strip_newline(contents);
+    if trimmed.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Passphrase can't be empty",
+        ));
+    }
+
+    Ok(SecretString::from(trimmed.to_string()))
+}
+
+/// A duplicate of an inherited file descriptor, so that reading it doesn't
+/// close the original (e.g. STDIN) for the rest of the process
+#[cfg(unix)]
+fn inherited_fd(fd: i32) -> Result<File, io::Error> {
+    use std::os::fd::BorrowedFd;
+
+    // SAFETY: F_GETFD only reads the flags of the file descriptor, if it's open
+    if fd < 0 || unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("File descriptor {fd} isn't open"),
+        ));
+    }
+
+    // SAFETY: the file descriptor is open, and it's only borrowed until it's
+    // duplicated
+    let borrowed = unsafe { BorrowedFd::borrow_raw(fd) };
+    Ok(File::from(borrowed.try_clone_to_owned()?))
+}
+
+/// Remove a single trailing newline (\n or \r\n)
+fn strip_newline(value: &str) -> &str {
+    value
+        .strip_suffix('\n')
+        .map(|v| v.strip_suffix('\r').unwrap_or(v))
+        .unwrap_or(value)
+}
+
+/// Refuse regular passphrase files that other users can access. Pipes and
+/// other special files are accepted as-is.
+#[cfg(unix)]
+fn check_permissions(metadata: &fs::Metadata, path: &std::path::Path) -> Result<(), io::Error> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = metadata.permissions().mode() & 0o777;
+    if metadata.is_file() && mode & 0o077 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "Passphrase file {} is accessible by other users (mode {mode:03o}), run chmod 600 on it first",
+                path.display()
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_permissions(_metadata: &fs::Metadata, _path: &std::path::Path) -> Result<(), io::Error> {
+    Ok(())
+}
+
+/// Show a message directly on the terminal, bypassing STDOUT (which may be
+/// the PDF output). Falls back to STDERR if there's no terminal.
+pub fn write_to_tty(message: &str) -> Result<(), io::Error> {
+    #[cfg(unix)]
+    let tty = OpenOptions::new().write(true).open("/dev/tty");
+    #[cfg(windows)]
+    let tty = OpenOptions::new().write(true).open("CONOUT$");
+    #[cfg(not(any(unix, windows)))]
+    let tty: Result<std::fs::File, io::Error> = Err(io::ErrorKind::Unsupported.into());
+
+    match tty {
+        Ok(mut tty) => writeln!(tty, "{message}"),
+        Err(_) => writeln!(io::stderr(), "{message}"),
+    }
+}
+
+/// Estimate the strength of the passphrase (offline, zxcvbn) and refuse it if
+/// the score (0–4) is below the given minimum
+pub fn check_strength(passphrase: &SecretString, min_score: u8) -> Result<(), io::Error> {
+    let entropy = zxcvbn(passphrase.expose_secret(), &[]);
+    let score = u8::from(entropy.score());
+
+    debug!(
+        "Passphrase score: {score}/4 (~10^{:.1} guesses)",
+        entropy.guesses_log10()
+    );
+
+    if score >= min_score {
+        return Ok(());
+    }
+
+    let mut message = format!("Passphrase is too weak (score {score}/4, minimum {min_score}/4)");
+    if let Some(feedback) = entropy.feedback() {
+        if let Some(warning) = feedback.warning() {
+            message.push_str(format!(": {warning}").as_str());
+        }
+        for suggestion in feedback.suggestions() {
+            message.push_str(format!(" {suggestion}").as_str());
+        }
+    }
+
+    Err(io::Error::new(io::ErrorKind::InvalidInput, message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_passphrase_from_env() -> Result<(), Box<dyn std::error::Error>> {
+        env::set_var("PAPERAGE_PASSPHRASE", "secret");
+
+        let result = get_passphrase(None, &Prompter::Terminal);
+        assert!(result.is_ok());
+
+        let passphrase = result?;
+        passphrase.expose_secret();
+
+        assert_eq!(passphrase.expose_secret(), "secret");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_env_trailing_newlines() -> Result<(), Box<dyn std::error::Error>> {
+        env::set_var("PAPERAGE_TEST_NEWLINES", "secret\r\n\n");
+
+        let passphrase = non_interactive_passphrase(None, "PAPERAGE_TEST_NEWLINES")?;
+        assert_eq!(passphrase.unwrap().expose_secret(), "secret");
+
+        assert!(non_interactive_passphrase(None, "PAPERAGE_TEST_UNSET")?.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_env_empty() {
+        for value in ["", "\n", "\r\n"] {
+            env::set_var("PAPERAGE_TEST_EMPTY", value);
+
+            let result = non_interactive_passphrase(None, "PAPERAGE_TEST_EMPTY");
+            let error = result.unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+            assert_eq!(error.to_string(), "Passphrase can't be empty");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_fd_not_open() {
+        let error = read_passphrase_source(&PassphraseSource::Fd(987)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(error.to_string(), "File descriptor 987 isn't open");
+    }
+
+    #[test]
+    fn test_normalize() {
+        // "crème brûlée" typed on macOS (NFD) and on Linux (NFC)
+        let nfd = SecretString::from(String::from("cre\u{300}me bru\u{302}le\u{301}e"));
+        let nfc = SecretString::from(String::from("cr\u{e8}me br\u{fb}l\u{e9}e"));
+
+        assert_eq!(normalize(nfd).expose_secret(), nfc.expose_secret());
+        assert_eq!(normalize(nfc.clone()).expose_secret(), nfc.expose_secret());
+    }
+
+    #[test]
+    fn test_typing_warnings() {
+        let passphrase = SecretString::from(String::from("sneaky-otter-tangerine-42"));
+        assert!(typing_warnings(&passphrase).is_empty());
+        assert_eq!(charset_hint(&passphrase), "The passphrase is ASCII-only");
+
+        let passphrase = SecretString::from(String::from("gr\u{fc}ne-otter^tangerine "));
+        let warnings = typing_warnings(&passphrase);
+        assert_eq!(warnings.len(), 3);
+        assert!(warnings.iter().all(|warning| !warning.contains('\u{fc}')));
+        assert!(charset_hint(&passphrase).contains("Unicode"));
+    }
+
+    #[test]
+    fn test_strip_newline() {
+        assert_eq!(strip_newline("secret\n"), "secret");
+        assert_eq!(strip_newline("secret\r\n"), "secret");
+        assert_eq!(strip_newline("secret\n\n"), "secret\n");
+        assert_eq!(strip_newline(" secret "), " secret ");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_passphrase_file() -> Result<(), Box<dyn std::error::Error>> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = env::temp_dir().join(format!("paper-age-passphrase-{}", std::process::id()));
+        fs::write(&path, "file secret\n")?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+
+        let source = PassphraseSource::File(path.clone());
+        let passphrase = get_passphrase(Some(&source), &Prompter::Terminal)?;
+        assert_eq!(passphrase.expose_secret(), "file secret");
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644))?;
+        let result = get_passphrase(Some(&source), &Prompter::Terminal);
+        fs::remove_file(&path)?;
+
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_weak_passphrase() {
+        let passphrase = SecretString::from(String::from("password1"));
+        let result = check_strength(&passphrase, 3);
+
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_strong_passphrase() {
+        let passphrase = SecretString::from(String::from("sneaky-otter-tangerine-42"));
+        assert!(check_strength(&passphrase, 3).is_ok());
+    }
+
+    #[test]
+    fn test_min_score_zero() {
+        let passphrase = SecretString::from(String::from("password1"));
+        assert!(check_strength(&passphrase, 0).is_ok());
+    }
+}
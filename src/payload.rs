@@ -0,0 +1,136 @@
+//! QR code payload encodings
+use std::io::{self, Read, Write};
+
+use age::armor::{ArmoredReader, ArmoredWriter, Format};
+
+mod qr_encoding;
+
+pub use qr_encoding::QrEncoding;
+
+/// Base45 alphabet (RFC 9285), all characters are in the QR alphanumeric set
+const BASE45_ALPHABET: &[u8; 45] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+
+/// Convert the armored ciphertext to the QR code payload
+pub fn encode(armored: &str, encoding: QrEncoding) -> Result<Vec<u8>, io::Error> {
+    match encoding {
+        QrEncoding::Armor => Ok(armored.as_bytes().to_vec()),
+        QrEncoding::Binary => dearmor(armored),
+        QrEncoding::Base45 => Ok(base45_encode(&dearmor(armored)?).into_bytes()),
+    }
+}
+
+/// Decode ASCII armored age ciphertext to the binary format
+pub fn dearmor(armored: &str) -> Result<Vec<u8>, io::Error> {
+    let mut binary = vec![];
+    ArmoredReader::new(armored.as_bytes()).read_to_end(&mut binary)?;
+
+    Ok(binary)
+}
+
+/// Encode the age binary format as ASCII armor, exactly as it's printed on a
+/// sheet
+pub fn armor(binary: &[u8]) -> Result<String, io::Error> {
+    let mut writer = ArmoredWriter::wrap_output(vec![], Format::AsciiArmor)?;
+    writer.write_all(binary)?;
+    let armored = writer.finish()?;
+
+    String::from_utf8(armored).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Encode bytes as base45 (RFC 9285)
+pub fn base45_encode(data: &[u8]) -> String {
+    let mut result = String::with_capacity(data.len() / 2 * 3 + 2);
+
+    for chunk in data.chunks(2) {
+        let (mut value, len) = match chunk {
+            [a, b] => (usize::from(*a) * 256 + usize::from(*b), 3),
+            [a] => (usize::from(*a), 2),
+            _ => unreachable!("chunks of one or two bytes"),
+        };
+
+        for _ in 0..len {
+            result.push(char::from(BASE45_ALPHABET[value % 45]));
+            value /= 45;
+        }
+    }
+
+    result
+}
+
+/// Decode base45 (RFC 9285) to bytes
+pub fn base45_decode(text: &str) -> Result<Vec<u8>, io::Error> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "Invalid base45 data");
+
+    let values = text
+        .bytes()
+        .map(|c| {
+            BASE45_ALPHABET
+                .iter()
+                .position(|&a| a == c)
+                .ok_or_else(invalid)
+        })
+        .collect::<Result<Vec<usize>, io::Error>>()?;
+
+    let mut result = Vec::with_capacity(values.len() / 3 * 2 + 1);
+    for chunk in values.chunks(3) {
+        match chunk {
+            [c, d, e] => {
+                let value = c + d * 45 + e * 45 * 45;
+                let bytes = u16::try_from(value).map_err(|_| invalid())?.to_be_bytes();
+                result.extend_from_slice(&bytes);
+            }
+            [c, d] => {
+                let value = c + d * 45;
+                result.push(u8::try_from(value).map_err(|_| invalid())?);
+            }
+            _ => return Err(invalid()),
+        }
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base45_rfc_examples() {
+        assert_eq!(base45_encode(b"AB"), "BB8");
+        assert_eq!(base45_encode(b"Hello!!"), "%69 VD92EX0");
+        assert_eq!(base45_encode(b"base-45"), "UJCLQE7W581");
+        assert_eq!(base45_decode("QED8WEX0").unwrap(), b"ietf!");
+    }
+
+    #[test]
+    fn test_base45_invalid() {
+        assert!(base45_decode("GGW").is_err());
+        assert!(base45_decode("abc").is_err());
+        assert!(base45_decode("A").is_err());
+    }
+
+    #[test]
+    fn test_dearmor() {
+        let armored =
+            "-----BEGIN AGE ENCRYPTED FILE-----\nSGVsbG8=\n-----END AGE ENCRYPTED FILE-----\n";
+        assert_eq!(dearmor(armored).unwrap(), b"Hello");
+    }
+
+    #[test]
+    fn test_armor() {
+        let armored =
+            "-----BEGIN AGE ENCRYPTED FILE-----\nSGVsbG8=\n-----END AGE ENCRYPTED FILE-----\n";
+        assert_eq!(armor(b"Hello").unwrap(), armored);
+    }
+
+    #[test]
+    fn test_encode_base45() {
+        let armored =
+            "-----BEGIN AGE ENCRYPTED FILE-----\nSGVsbG8=\n-----END AGE ENCRYPTED FILE-----\n";
+        let payload = encode(armored, QrEncoding::Base45).unwrap();
+        assert_eq!(
+            base45_decode(std::str::from_utf8(&payload).unwrap()).unwrap(),
+            b"Hello"
+        );
+    }
+}
@@ -0,0 +1,21 @@
+//! How the ciphertext is stored in the QR code
+use std::fmt;
+
+/// How the ciphertext is stored in the QR code
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QrEncoding {
+    /// ASCII armor (PEM), same as the printed text
+    Armor,
+
+    /// Raw age binary in byte mode
+    Binary,
+
+    /// Base45 encoded age binary in alphanumeric mode
+    Base45,
+}
+
+impl fmt::Display for QrEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", format!("{self:?}").to_lowercase())
+    }
+}
@@ -0,0 +1,219 @@
+//! Graphical passphrase entry with a GnuPG pinentry program (over the Assuan
+//! protocol) or an SSH_ASKPASS-style helper
+use std::{
+    env,
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use age::secrecy::{ExposeSecret, SecretString};
+use zeroize::Zeroizing;
+
+/// Title of the pinentry dialog
+const TITLE: &str = "PaperAge";
+
+/// Ask for a secret with a pinentry program
+pub fn get_pin(program: &Path, description: &str, prompt: &str) -> Result<SecretString, io::Error> {
+    debug!("Asking for the passphrase with {}", program.display());
+
+    let mut child = Command::new(program)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    let mut stdin = child.stdin.take().expect("pinentry stdin is piped");
+    let mut stdout = BufReader::new(child.stdout.take().expect("pinentry stdout is piped"));
+
+    let pin = converse(&mut stdin, &mut stdout, description, prompt);
+
+    // The pinentry exits by itself once its input is closed, also when the
+    // conversation failed half-way
+    writeln!(stdin, "BYE").ok();
+    drop(stdin);
+    child.wait()?;
+
+    match pin? {
+        Some(pin) if !pin.expose_secret().is_empty() => Ok(pin),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Passphrase can't be empty",
+        )),
+    }
+}
+
+/// Set up the pinentry dialog and ask for the PIN
+fn converse(
+    stdin: &mut impl Write,
+    stdout: &mut impl BufRead,
+    description: &str,
+    prompt: &str,
+) -> Result<Option<SecretString>, io::Error> {
+    // Greeting from the pinentry
+    read_response(stdout)?;
+
+    let mut commands = vec![
+        format!("SETTITLE {}", escape(TITLE)),
+        format!("SETDESC {}", escape(description)),
+        format!("SETPROMPT {}", escape(prompt)),
+    ];
+    if let Ok(tty) = env::var("GPG_TTY") {
+        commands.push(format!("OPTION ttyname={}", escape(&tty)));
+    }
+
+    for command in commands {
+        writeln!(stdin, "{command}")?;
+        read_response(stdout)?;
+    }
+
+    writeln!(stdin, "GETPIN")?;
+    read_response(stdout)
+}
+
+/// Ask for a secret with an SSH_ASKPASS-style helper, which gets the prompt as
+/// its only argument and prints the secret to STDOUT
+pub fn askpass(program: &Path, prompt: &str) -> Result<SecretString, io::Error> {
+    debug!("Asking for the passphrase with {}", program.display());
+
+    let output = Command::new(program)
+        .arg(prompt)
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()?;
+
+    if !output.status.success() {
+        return Err(io::Error::other("Passphrase entry was cancelled"));
+    }
+
+    let secret = SecretString::from(String::from_utf8(output.stdout).map_err(|_| {
+        io::Error::new(io::ErrorKind::InvalidData, "Passphrase is not valid UTF-8")
+    })?);
+    let trimmed = secret.expose_secret().trim_end_matches(['\r', '\n']);
+
+    if trimmed.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Passphrase can't be empty",
+        ));
+    }
+
+    Ok(SecretString::from(trimmed.to_string()))
+}
+
+/// Find a program in PATH
+pub fn find_program(name: &str) -> Option<PathBuf> {
+    let paths = env::var_os("PATH")?;
+
+    env::split_paths(&paths)
+        .map(|dir| dir.join(format!("{name}{}", env::consts::EXE_SUFFIX)))
+        .find(|path| path.is_file())
+}
+
+/// Read Assuan response lines until OK or ERR. Returns the data lines, if any.
+fn read_response(reader: &mut impl BufRead) -> Result<Option<SecretString>, io::Error> {
+    let mut data: Option<Zeroizing<String>> = None;
+
+    loop {
+        let mut line = Zeroizing::new(String::new());
+        if reader.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "The pinentry exited unexpectedly",
+            ));
+        }
+        let line = line.trim_end_matches(['\r', '\n']);
+
+        if line == "OK" || line.starts_with("OK ") {
+            return Ok(data.map(|value| SecretString::from(value.as_str())));
+        } else if let Some(error) = line.strip_prefix("ERR ") {
+            return Err(assuan_error(error));
+        } else if let Some(value) = line.strip_prefix("D ") {
+            let value = unescape(value);
+            match &mut data {
+                Some(previous) => previous.push_str(&value),
+                None => data = Some(value),
+            }
+        }
+        // Status (S) and comment (#) lines are ignored
+    }
+}
+
+/// Convert an Assuan error (e.g. `83886179 Operation cancelled`) to an
+/// io::Error
+fn assuan_error(error: &str) -> io::Error {
+    let (code, description) = error.split_once(' ').unwrap_or((error, ""));
+
+    // The low 16 bits are the GPG error code, 99 is GPG_ERR_CANCELED
+    match code.parse::<u32>() {
+        Ok(code) if code & 0xffff == 99 => io::Error::other("Passphrase entry was cancelled"),
+        _ => io::Error::other(format!("pinentry error: {description} ({code})")),
+    }
+}
+
+/// Percent-escape a string for an Assuan command
+fn escape(value: &str) -> String {
+    value
+        .replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Decode a percent-escaped Assuan data line
+fn unescape(value: &str) -> Zeroizing<String> {
+    let bytes = value.as_bytes();
+    let mut result = Zeroizing::new(Vec::with_capacity(bytes.len()));
+
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or_default();
+            if let Ok(byte) = u8::from_str_radix(hex, 16) {
+                result.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        result.push(bytes[i]);
+        i += 1;
+    }
+
+    Zeroizing::new(String::from_utf8_lossy(&result).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("100% sure\nreally"), "100%25 sure%0Areally");
+    }
+
+    #[test]
+    fn test_unescape() {
+        assert_eq!(*unescape("100%25 sure%0Areally"), "100% sure\nreally");
+        assert_eq!(*unescape("trailing%"), "trailing%");
+        assert_eq!(*unescape("%zz"), "%zz");
+    }
+
+    #[test]
+    fn test_read_response() -> Result<(), Box<dyn std::error::Error>> {
+        let mut response = "S PASSWORD_FROM_CACHE\nD top%25secret\nOK\n".as_bytes();
+        let pin = read_response(&mut response)?;
+        assert_eq!(pin.unwrap().expose_secret(), "top%secret");
+
+        let mut response = "D top\nD %25secret\nOK\n".as_bytes();
+        let pin = read_response(&mut response)?;
+        assert_eq!(pin.unwrap().expose_secret(), "top%secret");
+
+        let mut response = "ERR 83886179 Operation cancelled <Pinentry>\n".as_bytes();
+        let result = read_response(&mut response);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Passphrase entry was cancelled"));
+
+        Ok(())
+    }
+}
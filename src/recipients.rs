@@ -0,0 +1,386 @@
+//! Age recipients
+use std::{
+    env, fs,
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use age::secrecy::SecretString;
+use base64::{prelude::BASE64_STANDARD_NO_PAD, Engine};
+use rpassword::prompt_password;
+use sha2::{Digest, Sha256};
+
+/// Prefixes of the OpenSSH public key types. age only supports ssh-ed25519 and
+/// ssh-rsa, the others (e.g. ECDSA and security keys) are unsupported.
+const SSH_KEY_PREFIXES: [&str; 4] = ["ssh-", "ecdsa-sha2-", "sk-ssh-", "sk-ecdsa-sha2-"];
+
+/// A recipient and the short label that is printed on the sheet
+pub struct Recipient {
+    /// The age recipient used for wrapping the file key
+    pub recipient: Box<dyn age::Recipient + Send>,
+
+    /// Short fingerprint of the recipient for the sheet
+    pub fingerprint: String,
+}
+
+/// Parse a single recipient string (e.g. age1…, age1name1…, or an OpenSSH
+/// public key)
+pub fn parse_recipient(value: &str) -> Result<Recipient, io::Error> {
+    let value = strip_key_options(value.trim());
+
+    if value.starts_with("ssh-") {
+        return parse_ssh_recipient(value);
+    }
+
+    if is_ssh_key(value) {
+        let key_type = value.split_whitespace().next().unwrap_or_default();
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("Unsupported SSH key type: {key_type}"),
+        ));
+    }
+
+    match age::x25519::Recipient::from_str(value) {
+        Ok(recipient) => Ok(Recipient {
+            recipient: Box::new(recipient),
+            fingerprint: fingerprint(value),
+        }),
+        Err(e) => match age::plugin::Recipient::from_str(value) {
+            Ok(recipient) => parse_plugin_recipient(recipient),
+            Err(_) => Err(invalid_recipient(value, e)),
+        },
+    }
+}
+
+/// Set up a plugin recipient (age1name1…). The matching age-plugin-name
+/// binary must be in PATH.
+fn parse_plugin_recipient(recipient: age::plugin::Recipient) -> Result<Recipient, io::Error> {
+    let plugin_name = recipient.plugin().to_string();
+    let value = recipient.to_string();
+
+    debug!("Using plugin: age-plugin-{plugin_name}");
+
+    match age::plugin::RecipientPluginV1::new(&plugin_name, &[recipient], &[], PluginCallbacks) {
+        Ok(plugin) => Ok(Recipient {
+            recipient: Box::new(plugin),
+            fingerprint: fingerprint(&value),
+        }),
+        Err(age::EncryptError::MissingPlugin { binary_name }) => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Could not find '{binary_name}' in PATH for recipient {value}"),
+        )),
+        Err(e) => Err(io::Error::other(format!("{e}"))),
+    }
+}
+
+/// Parse an OpenSSH public key (ssh-ed25519 or ssh-rsa). The key type and
+/// comment are kept for the sheet.
+fn parse_ssh_recipient(value: &str) -> Result<Recipient, io::Error> {
+    let recipient = match age::ssh::Recipient::from_str(value) {
+        Ok(recipient) => recipient,
+        Err(age::ssh::ParseRecipientKeyError::Invalid(e)) => {
+            return Err(invalid_recipient(value, e));
+        }
+        Err(age::ssh::ParseRecipientKeyError::RsaModulusTooLarge) => {
+            return Err(invalid_recipient(value, "RSA modulus too large"));
+        }
+        Err(age::ssh::ParseRecipientKeyError::RsaModulusTooSmall) => {
+            return Err(invalid_recipient(value, "RSA modulus too small"));
+        }
+        Err(age::ssh::ParseRecipientKeyError::Unsupported(key_type)) => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("Unsupported SSH key type: {key_type}"),
+            ));
+        }
+        Err(age::ssh::ParseRecipientKeyError::Ignore) => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Unsupported SSH key",
+            ));
+        }
+    };
+
+    let mut parts = value.split_whitespace();
+    let key_type = parts.next().unwrap_or_default();
+    let key_data = parts.next().unwrap_or_default();
+    let comment = parts.collect::<Vec<&str>>().join(" ");
+
+    let fingerprint = [key_type.to_string(), ssh_fingerprint(key_data), comment]
+        .iter()
+        .filter(|s| !s.is_empty())
+        .cloned()
+        .collect::<Vec<String>>()
+        .join(" ");
+
+    Ok(Recipient {
+        recipient: Box::new(recipient),
+        fingerprint,
+    })
+}
+
+/// Read the recipients from a recipients file, one recipient per line. Empty
+/// lines and comments (starting with #) are ignored. OpenSSH authorized_keys
+/// files are supported too.
+pub fn read_recipients_file(path: &Path) -> Result<Vec<Recipient>, io::Error> {
+    debug!("Reading recipients file: {}", path.display());
+
+    let reader = BufReader::new(fs::File::open(path)?);
+
+    let mut recipients = vec![];
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        recipients.push(parse_recipient(line)?);
+    }
+
+    if recipients.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("No recipients found in {}", path.display()),
+        ));
+    }
+
+    Ok(recipients)
+}
+
+/// Read all the supported SSH public keys (*.pub) from the ~/.ssh directory.
+/// Unsupported key types are skipped.
+pub fn read_ssh_public_keys() -> Result<Vec<Recipient>, io::Error> {
+    let home = env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Home directory not found"))?;
+    let ssh_dir = PathBuf::from(home).join(".ssh");
+
+    debug!("Reading SSH public keys from {}", ssh_dir.display());
+
+    let mut paths: Vec<PathBuf> = fs::read_dir(&ssh_dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "pub"))
+        .collect();
+    paths.sort();
+
+    let mut recipients = vec![];
+    for path in paths {
+        let contents = fs::read_to_string(&path)?;
+        match parse_recipient(&contents) {
+            Ok(recipient) => recipients.push(recipient),
+            Err(e) if e.kind() == io::ErrorKind::Unsupported => {
+                warn!("Skipping {}: {e}", path.display());
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    if recipients.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "No supported SSH public keys found in {}",
+                ssh_dir.display()
+            ),
+        ));
+    }
+
+    Ok(recipients)
+}
+
+/// Shorten a recipient string to something that fits on the sheet but can
+/// still be matched to the full recipient
+pub fn fingerprint(value: &str) -> String {
+    const HEAD_LEN: usize = 12;
+    const TAIL_LEN: usize = 8;
+
+    if value.len() <= HEAD_LEN + TAIL_LEN + 3 {
+        return value.to_string();
+    }
+
+    format!(
+        "{}...{}",
+        &value[..HEAD_LEN],
+        &value[value.len() - TAIL_LEN..]
+    )
+}
+
+/// Shortened SHA256 fingerprint of an SSH key, matching the beginning of the
+/// `ssh-keygen -l` output
+fn ssh_fingerprint(key_data: &str) -> String {
+    const FINGERPRINT_LEN: usize = 10;
+
+    match base64::prelude::BASE64_STANDARD.decode(key_data) {
+        Ok(blob) => {
+            let hash = BASE64_STANDARD_NO_PAD.encode(Sha256::digest(blob));
+            format!("SHA256:{}...", &hash[..FINGERPRINT_LEN])
+        }
+        Err(_) => String::new(),
+    }
+}
+
+/// Callbacks for interacting with the user on behalf of age plugins
+#[derive(Clone)]
+pub struct PluginCallbacks;
+
+impl age::Callbacks for PluginCallbacks {
+    fn display_message(&self, message: &str) {
+        warn!("{message}");
+    }
+
+    fn confirm(&self, message: &str, yes_string: &str, no_string: Option<&str>) -> Option<bool> {
+        let prompt = match no_string {
+            Some(no_string) => format!("{message} [{yes_string}/{no_string}]: "),
+            None => format!("{message} [{yes_string}]: "),
+        };
+
+        let answer = read_line_from_tty(&prompt).ok()?;
+        if answer.trim() == yes_string {
+            Some(true)
+        } else if no_string.is_some_and(|no| answer.trim() == no) {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    fn request_public_string(&self, description: &str) -> Option<String> {
+        read_line_from_tty(&format!("{description}: ")).ok()
+    }
+
+    fn request_passphrase(&self, description: &str) -> Option<SecretString> {
+        prompt_password(format!("{description}: "))
+            .ok()
+            .map(SecretString::from)
+    }
+}
+
+/// Read a line of public input from the terminal, shown as it's typed
+fn read_line_from_tty(prompt: &str) -> Result<String, io::Error> {
+    #[cfg(unix)]
+    let (input, output) = ("/dev/tty", "/dev/tty");
+    #[cfg(windows)]
+    let (input, output) = ("CONIN$", "CONOUT$");
+    #[cfg(not(any(unix, windows)))]
+    return Err(io::ErrorKind::Unsupported.into());
+
+    #[cfg(any(unix, windows))]
+    {
+        let mut output = fs::OpenOptions::new().write(true).open(output)?;
+        write!(output, "{prompt}")?;
+        output.flush()?;
+
+        let mut line = String::new();
+        BufReader::new(fs::File::open(input)?).read_line(&mut line)?;
+
+        Ok(line.trim_end_matches(['\r', '\n']).to_string())
+    }
+}
+
+/// Check if the value starts with an OpenSSH public key type
+fn is_ssh_key(value: &str) -> bool {
+    SSH_KEY_PREFIXES
+        .iter()
+        .any(|prefix| value.starts_with(prefix))
+}
+
+/// Remove any authorized_keys options (e.g. `no-pty,from="…"`) in front of an
+/// SSH public key
+fn strip_key_options(value: &str) -> &str {
+    if is_ssh_key(value) || value.starts_with("age1") {
+        return value;
+    }
+
+    let start = SSH_KEY_PREFIXES
+        .iter()
+        .filter_map(|prefix| value.find(&format!(" {prefix}")))
+        .min();
+
+    match start {
+        Some(index) => &value[index + 1..],
+        None => value,
+    }
+}
+
+/// Error for a recipient that cannot be parsed
+fn invalid_recipient(value: &str, reason: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Invalid recipient '{value}': {reason}"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RECIPIENT: &str = "age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p";
+
+    const SSH_RECIPIENT: &str = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIHsKLqeplhpW+uObz5dvMgjz1OxfM/XXUB+VHtZ6isGN alice@rust";
+
+    #[test]
+    fn test_parse_recipient() {
+        let result = parse_recipient(RECIPIENT);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().fingerprint, "age1ql3z7hjy...aqmcac8p");
+    }
+
+    #[test]
+    fn test_parse_invalid_recipient() {
+        let result = parse_recipient("age1notarecipient");
+        assert!(result.is_err());
+        assert_eq!(result.err().unwrap().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_parse_ssh_recipient() {
+        let result = parse_recipient(SSH_RECIPIENT);
+        assert!(result.is_ok());
+
+        let fingerprint = result.unwrap().fingerprint;
+        assert!(fingerprint.starts_with("ssh-ed25519 SHA256:"));
+        assert!(fingerprint.ends_with(" alice@rust"));
+    }
+
+    #[test]
+    fn test_parse_authorized_keys_line() {
+        let line = format!("no-pty,from=\"10.0.0.0/8\" {SSH_RECIPIENT}");
+        let result = parse_recipient(&line);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_parse_unsupported_ssh_recipient() {
+        let result = parse_recipient("ssh-dss AAAAB3NzaC1kc3MAAACBAP comment");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_unsupported_ssh_key_types() {
+        for value in [
+            "ecdsa-sha2-nistp256 AAAAE2VjZHNhLXNoYTItbmlzdHAyNTYAAAAIbmlzdHAyNTYAAABBBDGEzo4A2+EN3AL2siT8kG4oxO8rdXmTAWQ1RThP8tStrlcVNXswcLThLdYAFhRC7pIELBVt/EUuGL5lDzcIHYA= bob@laptop",
+            "sk-ssh-ed25519@openssh.com AAAAGnNrLXNzaC1lZDI1NTE5QG9wZW5zc2guY29tAAAAIAABAgMEBQYHCAkKCwwNDg8QERITFBUWFxgZGhscHR4fAAAABHNzaDo= carol@yubikey",
+            "restrict ecdsa-sha2-nistp384 AAAA dave@server",
+        ] {
+            let result = parse_recipient(value);
+            assert_eq!(result.err().unwrap().kind(), io::ErrorKind::Unsupported);
+        }
+    }
+
+    #[test]
+    fn test_parse_missing_plugin_recipient() {
+        let result = parse_recipient(
+            "age1missing1wpshqetj94skwefqw3jhxapqwd682c3qwpk82emfdcsxketeyyssacd2zz",
+        );
+        assert!(result.is_err());
+        assert_eq!(result.err().unwrap().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_fingerprint_short_value() {
+        assert_eq!(fingerprint("age1short"), "age1short");
+    }
+}
@@ -0,0 +1,297 @@
+//! Shamir's secret sharing over GF(256)
+use std::io;
+
+use rand::{rngs::OsRng, RngCore};
+use zeroize::Zeroize;
+
+/// Marker at the start of a share plaintext. The NUL byte keeps it from
+/// clashing with text inputs.
+pub const MARKER: &[u8] = b"\x00PAS";
+
+/// Version of the share format
+const VERSION: u8 = 1;
+
+/// Marker, version, set ID, threshold, total, and index
+const HEADER_LEN: usize = MARKER.len() + 1 + 4 + 3;
+
+/// A single share of a secret
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Share {
+    /// Random ID shared by all the shares of the same secret
+    pub set_id: [u8; 4],
+
+    /// Number of shares needed to recover the secret
+    pub threshold: u8,
+
+    /// Total number of shares
+    pub total: u8,
+
+    /// Index (x coordinate) of the share, from 1 to total
+    pub index: u8,
+
+    /// Share data, as long as the secret
+    pub data: Vec<u8>,
+}
+
+impl Drop for Share {
+    fn drop(&mut self) {
+        self.data.zeroize();
+    }
+}
+
+impl Share {
+    /// Serialize the share for encryption
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN + self.data.len());
+        bytes.extend_from_slice(MARKER);
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&self.set_id);
+        bytes.extend_from_slice(&[self.threshold, self.total, self.index]);
+        bytes.extend_from_slice(&self.data);
+
+        bytes
+    }
+
+    /// Parse a serialized (decrypted) share
+    pub fn from_bytes(bytes: &[u8]) -> Result<Share, io::Error> {
+        if !is_share(bytes) || bytes.len() < HEADER_LEN {
+            return Err(invalid_share("Not a PaperAge share"));
+        }
+
+        let version = bytes[MARKER.len()];
+        if version != VERSION {
+            return Err(invalid_share(&format!(
+                "Unsupported share version {version}"
+            )));
+        }
+
+        let header = &bytes[MARKER.len() + 1..HEADER_LEN];
+        let share = Share {
+            set_id: header[..4].try_into().expect("4 byte set ID"),
+            threshold: header[4],
+            total: header[5],
+            index: header[6],
+            data: bytes[HEADER_LEN..].to_vec(),
+        };
+
+        if share.threshold < 2
+            || share.index == 0
+            || share.index > share.total
+            || share.threshold > share.total
+        {
+            return Err(invalid_share("Inconsistent share header"));
+        }
+
+        Ok(share)
+    }
+
+    /// Set ID as hex
+    pub fn set_id_hex(&self) -> String {
+        self.set_id.iter().map(|b| format!("{b:02x}")).collect()
+    }
+}
+
+/// Check if the (decrypted) plaintext is a PaperAge share
+pub fn is_share(bytes: &[u8]) -> bool {
+    bytes.starts_with(MARKER)
+}
+
+/// Split the secret into the total number of shares, any threshold of which
+/// can recover it
+pub fn split(secret: &[u8], threshold: u8, total: u8) -> Result<Vec<Share>, io::Error> {
+    if threshold < 2 || threshold > total {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid threshold: {threshold} of {total} shares"),
+        ));
+    }
+
+    let mut set_id = [0; 4];
+    OsRng.fill_bytes(&mut set_id);
+
+    let mut shares: Vec<Share> = (1..=total)
+        .map(|index| Share {
+            set_id,
+            threshold,
+            total,
+            index,
+            data: Vec::with_capacity(secret.len()),
+        })
+        .collect();
+
+    // One random polynomial of degree threshold - 1 per byte, with the secret
+    // byte as the constant term
+    let mut coefficients = vec![0; usize::from(threshold)];
+    for byte in secret {
+        coefficients[0] = *byte;
+        OsRng.fill_bytes(&mut coefficients[1..]);
+
+        for share in shares.iter_mut() {
+            let y = coefficients
+                .iter()
+                .rev()
+                .fold(0, |acc, c| gf_mul(acc, share.index) ^ c);
+            share.data.push(y);
+        }
+    }
+    coefficients.zeroize();
+
+    Ok(shares)
+}
+
+/// Recover the secret from at least threshold shares of the same set
+pub fn combine(shares: &[Share]) -> Result<Vec<u8>, io::Error> {
+    let first = shares
+        .first()
+        .ok_or_else(|| invalid_share("No shares given"))?;
+
+    let mut indexes = vec![];
+    for share in shares {
+        if share.set_id != first.set_id {
+            return Err(invalid_share(&format!(
+                "Shares from different sets: {} and {}",
+                first.set_id_hex(),
+                share.set_id_hex()
+            )));
+        }
+        if share.threshold != first.threshold || share.data.len() != first.data.len() {
+            return Err(invalid_share("Inconsistent shares"));
+        }
+        if indexes.contains(&share.index) {
+            return Err(invalid_share(&format!("Duplicate share {}", share.index)));
+        }
+        indexes.push(share.index);
+    }
+
+    if shares.len() < usize::from(first.threshold) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Not enough shares: {} of {} needed",
+                shares.len(),
+                first.threshold
+            ),
+        ));
+    }
+
+    // Lagrange interpolation at x = 0
+    let shares = &shares[..usize::from(first.threshold)];
+    let mut secret = vec![0; first.data.len()];
+    for (i, share) in shares.iter().enumerate() {
+        let basis = shares
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| *j != i)
+            .fold(1, |acc, (_, other)| {
+                gf_mul(acc, gf_div(other.index, other.index ^ share.index))
+            });
+
+        for (byte, y) in secret.iter_mut().zip(share.data.iter()) {
+            *byte ^= gf_mul(basis, *y);
+        }
+    }
+
+    Ok(secret)
+}
+
+/// Multiplication in GF(256) with the AES polynomial (x^8 + x^4 + x^3 + x + 1)
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut result = 0;
+
+    for _ in 0..8 {
+        // Branchless versions of `if b & 1 { result ^= a }` and the reduction
+        result ^= a & 0u8.wrapping_sub(b & 1);
+        let carry = 0u8.wrapping_sub(a >> 7);
+        a = (a << 1) ^ (0x1b & carry);
+        b >>= 1;
+    }
+
+    result
+}
+
+/// Division in GF(256), b must not be zero
+fn gf_div(a: u8, b: u8) -> u8 {
+    // b^254 = b^-1
+    let mut inverse = 1;
+    for _ in 0..254 {
+        inverse = gf_mul(inverse, b);
+    }
+
+    gf_mul(a, inverse)
+}
+
+/// Error for a malformed or mismatched share
+fn invalid_share(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &[u8] = b"correct horse battery staple";
+
+    #[test]
+    fn test_gf_arithmetic() {
+        assert_eq!(gf_mul(0x53, 0xca), 0x01);
+        assert_eq!(gf_div(0x01, 0x53), 0xca);
+        assert_eq!(gf_mul(0x57, 0x83), 0xc1);
+    }
+
+    #[test]
+    fn test_split_and_combine() -> Result<(), io::Error> {
+        let shares = split(SECRET, 3, 5)?;
+        assert_eq!(shares.len(), 5);
+        assert!(shares.iter().all(|s| s.set_id == shares[0].set_id));
+
+        let subset = vec![shares[4].clone(), shares[0].clone(), shares[2].clone()];
+        assert_eq!(combine(&subset)?, SECRET);
+
+        let subset = vec![shares[1].clone(), shares[3].clone()];
+        assert_eq!(
+            combine(&subset).unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_share_serialization() -> Result<(), io::Error> {
+        let shares = split(SECRET, 2, 3)?;
+        let bytes = shares[1].to_bytes();
+
+        assert!(is_share(&bytes));
+        assert_eq!(Share::from_bytes(&bytes)?, shares[1]);
+        assert!(Share::from_bytes(b"Hello").is_err());
+
+        // A threshold below 2 would "recover" all zeros from a single share
+        for threshold in [0, 1] {
+            let mut bytes = shares[1].to_bytes();
+            bytes[MARKER.len() + 5] = threshold;
+            assert_eq!(
+                Share::from_bytes(&bytes).unwrap_err().kind(),
+                io::ErrorKind::InvalidData
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_different_sets() -> Result<(), io::Error> {
+        let first = split(SECRET, 2, 2)?;
+        let second = split(SECRET, 2, 2)?;
+
+        let result = combine(&[first[0].clone(), second[1].clone()]);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_threshold() {
+        assert!(split(SECRET, 1, 3).is_err());
+        assert!(split(SECRET, 4, 3).is_err());
+    }
+}
@@ -0,0 +1,186 @@
+//! SSH signatures of the ciphertext, compatible with `ssh-keygen -Y sign`
+use std::{fs, io, path::Path};
+
+use age::secrecy::ExposeSecret;
+use base64::{
+    engine::{general_purpose::STANDARD, DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+    Engine,
+};
+use ssh_key::{HashAlg, LineEnding, PrivateKey, PublicKey, SshSig};
+
+use crate::passphrase::Prompter;
+
+/// Signature namespace, as in `ssh-keygen -Y sign -n paper-age`
+pub const NAMESPACE: &str = "paper-age";
+
+/// Armor begin and end markers, without whitespace
+const BEGIN_MARKER: &str = "-----BEGINSSHSIGNATURE-----";
+const END_MARKER: &str = "-----ENDSSHSIGNATURE-----";
+
+/// Line width of the armor, as written by ssh-keygen
+const LINE_WIDTH: usize = 70;
+
+/// Base64 that doesn't care about missing or extra padding
+const LENIENT_BASE64: GeneralPurpose = GeneralPurpose::new(
+    &base64::alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// Read an OpenSSH private key for signing. Asks for the passphrase if the key
+/// is encrypted.
+pub fn read_signing_key(path: &Path, prompter: &Prompter) -> Result<PrivateKey, io::Error> {
+    debug!("Reading the signing key from {}", path.display());
+
+    let key = PrivateKey::read_openssh_file(path).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Could not read the SSH private key {}: {e}", path.display()),
+        )
+    })?;
+
+    if !key.is_encrypted() {
+        return Ok(key);
+    }
+
+    let passphrase = prompter.read_secret(&format!("Passphrase for {}", path.display()))?;
+    key.decrypt(passphrase.expose_secret()).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("Could not decrypt {}, check the passphrase", path.display()),
+        )
+    })
+}
+
+/// Read a trusted OpenSSH public key (e.g. id_ed25519.pub)
+pub fn read_public_key(path: &Path) -> Result<PublicKey, io::Error> {
+    debug!("Reading the public key from {}", path.display());
+
+    let contents = fs::read_to_string(path)?;
+    PublicKey::from_openssh(contents.trim()).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Could not read the SSH public key {}: {e}", path.display()),
+        )
+    })
+}
+
+/// Sign the ASCII armored ciphertext and return the armored signature
+pub fn sign(key: &PrivateKey, armored: &str) -> Result<String, io::Error> {
+    key.sign(NAMESPACE, HashAlg::Sha512, armored.as_bytes())
+        .and_then(|signature| signature.to_pem(LineEnding::LF))
+        .map_err(|e| io::Error::other(format!("Signing failed: {e}")))
+}
+
+/// Find an armored signature in typed or pasted text. Whitespace and any text
+/// around the armor are ignored.
+pub fn find_signature(text: &str) -> Result<Option<SshSig>, io::Error> {
+    let compact: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+
+    let Some(start) = compact.find(BEGIN_MARKER) else {
+        return Ok(None);
+    };
+    let start = start + BEGIN_MARKER.len();
+    let end = compact[start..]
+        .find(END_MARKER)
+        .map(|end| start + end)
+        .ok_or_else(|| {
+            invalid_signature("The end of the signature (-----END SSH SIGNATURE-----) is missing")
+        })?;
+
+    let binary = LENIENT_BASE64
+        .decode(&compact[start..end])
+        .map_err(|e| invalid_signature(&format!("Invalid signature armor: {e}")))?;
+
+    // Re-armor for the strict PEM parser
+    let encoded = STANDARD.encode(binary);
+    let lines: Vec<&str> = encoded
+        .as_bytes()
+        .chunks(LINE_WIDTH)
+        .map(|line| std::str::from_utf8(line).expect("base64 is ASCII"))
+        .collect();
+    let pem = format!(
+        "-----BEGIN SSH SIGNATURE-----\n{}\n-----END SSH SIGNATURE-----\n",
+        lines.join("\n")
+    );
+
+    SshSig::from_pem(pem)
+        .map(Some)
+        .map_err(|e| invalid_signature(&format!("Invalid signature: {e}")))
+}
+
+/// Verify the signature of the ASCII armored ciphertext with a trusted public
+/// key
+pub fn verify(key: &PublicKey, armored: &str, signature: &SshSig) -> Result<(), io::Error> {
+    if signature.public_key() != key.key_data() {
+        return Err(invalid_signature(&format!(
+            "The sheet wasn't signed with the trusted key {}",
+            key.fingerprint(HashAlg::Sha256)
+        )));
+    }
+
+    key.verify(NAMESPACE, armored.as_bytes(), signature)
+        .map_err(|_| invalid_signature("Bad signature, the ciphertext doesn't match it"))
+}
+
+/// Error for a malformed or mismatched signature
+fn invalid_signature(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ARMORED: &str =
+        "-----BEGIN AGE ENCRYPTED FILE-----\nSGVsbG8=\n-----END AGE ENCRYPTED FILE-----\n";
+
+    fn key() -> PrivateKey {
+        PrivateKey::random(&mut rand::rngs::OsRng, ssh_key::Algorithm::Ed25519).unwrap()
+    }
+
+    #[test]
+    fn test_sign_and_verify() -> Result<(), io::Error> {
+        let key = key();
+        let armored_signature = sign(&key, ARMORED)?;
+        assert!(armored_signature.starts_with("-----BEGIN SSH SIGNATURE-----\n"));
+
+        // As typed from the sheet
+        let typed = armored_signature
+            .lines()
+            .map(|line| format!("  {line}\r\n"))
+            .collect::<String>();
+        let signature = find_signature(&typed)?.unwrap();
+
+        verify(key.public_key(), ARMORED, &signature)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tampered_ciphertext() -> Result<(), io::Error> {
+        let key = key();
+        let signature = find_signature(&sign(&key, ARMORED)?)?.unwrap();
+        let tampered = ARMORED.replace("SGVsbG8=", "SGVsbG9=");
+
+        assert!(verify(key.public_key(), &tampered, &signature).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_untrusted_key() -> Result<(), io::Error> {
+        let signature = find_signature(&sign(&key(), ARMORED)?)?.unwrap();
+
+        assert!(verify(key().public_key(), ARMORED, &signature).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_no_signature() -> Result<(), io::Error> {
+        assert!(find_signature(ARMORED)?.is_none());
+        assert!(find_signature("-----BEGIN SSH SIGNATURE-----\nU1NI").is_err());
+
+        Ok(())
+    }
+}
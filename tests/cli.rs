@@ -2,6 +2,8 @@ use assert_cmd::Command;
 use assert_fs::prelude::*;
 use predicates::prelude::*;
 
+const PASSPHRASE: &str = "sneaky-otter-tangerine-42";
+
 #[test]
 fn test_happy_path() -> Result<(), Box<dyn std::error::Error>> {
     let temp = assert_fs::TempDir::new().unwrap();
@@ -14,7 +16,7 @@ fn test_happy_path() -> Result<(), Box<dyn std::error::Error>> {
         .arg(output.path())
         .arg("--grid")
         .arg(input.path())
-        .env("PAPERAGE_PASSPHRASE", "secret");
+        .env("PAPERAGE_PASSPHRASE", PASSPHRASE);
     cmd.assert().success();
 
     output.assert(predicate::path::is_file());
@@ -22,6 +24,26 @@ fn test_happy_path() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+#[test]
+fn test_fingerprint() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let input = temp.child("sample.txt");
+    input.write_str("Hello")?;
+    let output = temp.child("output.pdf");
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("--output")
+        .arg(output.path())
+        .arg("-vv")
+        .arg(input.path())
+        .env("PAPERAGE_PASSPHRASE", PASSPHRASE);
+    cmd.assert().success().stderr(
+        predicate::str::is_match("Ciphertext fingerprint: [0-9a-f]{4}(-[0-9a-f]{4})+").unwrap(),
+    );
+
+    Ok(())
+}
+
 #[test]
 fn test_letter_support() -> Result<(), Box<dyn std::error::Error>> {
     let temp = assert_fs::TempDir::new().unwrap();
@@ -35,7 +57,7 @@ fn test_letter_support() -> Result<(), Box<dyn std::error::Error>> {
         .arg("--page-size")
         .arg("letter")
         .arg(input.path())
-        .env("PAPERAGE_PASSPHRASE", "secret");
+        .env("PAPERAGE_PASSPHRASE", PASSPHRASE);
     cmd.assert().success();
 
     output.assert(predicate::path::is_file());
@@ -57,7 +79,7 @@ fn test_stdout() -> Result<(), Box<dyn std::error::Error>> {
     cmd.arg("--output")
         .arg("-")
         .arg(input.path())
-        .env("PAPERAGE_PASSPHRASE", "secret");
+        .env("PAPERAGE_PASSPHRASE", PASSPHRASE);
     cmd.assert().stdout(len_predicate_fn).success();
 
     Ok(())
@@ -92,7 +114,7 @@ fn test_too_much_data() -> Result<(), Box<dyn std::error::Error>> {
     cmd.arg("--output")
         .arg(output.path())
         .arg(input.path())
-        .env("PAPERAGE_PASSPHRASE", "secret");
+        .env("PAPERAGE_PASSPHRASE", PASSPHRASE);
     cmd.assert()
         .failure()
         .stderr(predicate::str::contains("Too much data after encryption"));
@@ -126,3 +148,1611 @@ fn test_title_too_long() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+fn test_recipients() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let input = temp.child("sample.txt");
+    input.write_str("Hello")?;
+    let recipients = temp.child("recipients.txt");
+    recipients.write_str(
+        "# Offline recovery key\nage1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p\n",
+    )?;
+    let output = temp.child("output.pdf");
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("--output")
+        .arg(output.path())
+        .arg("--recipient")
+        .arg("age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p")
+        .arg("--recipients-file")
+        .arg(recipients.path())
+        .arg(input.path());
+    cmd.assert().success();
+
+    output.assert(predicate::path::is_file());
+
+    Ok(())
+}
+
+#[test]
+fn test_invalid_recipient() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let input = temp.child("sample.txt");
+    input.write_str("Hello")?;
+    let output = temp.child("output.pdf");
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("--output")
+        .arg(output.path())
+        .arg("--recipient")
+        .arg("age1invalid")
+        .arg(input.path());
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid recipient"));
+
+    output.assert(predicate::path::missing());
+
+    Ok(())
+}
+
+#[test]
+fn test_ssh_keys() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let input = temp.child("sample.txt");
+    input.write_str("Hello")?;
+    let public_key = temp.child(".ssh/id_ed25519.pub");
+    public_key.write_str("ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIHsKLqeplhpW+uObz5dvMgjz1OxfM/XXUB+VHtZ6isGN alice@rust\n")?;
+    let output = temp.child("output.pdf");
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("--output")
+        .arg(output.path())
+        .arg("--ssh-keys")
+        .arg(input.path())
+        .env("HOME", temp.path());
+    cmd.assert().success();
+
+    output.assert(predicate::path::is_file());
+
+    Ok(())
+}
+
+#[test]
+fn test_ssh_keys_unsupported_types() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let input = temp.child("sample.txt");
+    input.write_str("Hello")?;
+    temp.child(".ssh/id_ecdsa.pub").write_str("ecdsa-sha2-nistp256 AAAAE2VjZHNhLXNoYTItbmlzdHAyNTYAAAAIbmlzdHAyNTYAAABBBDGEzo4A2+EN3AL2siT8kG4oxO8rdXmTAWQ1RThP8tStrlcVNXswcLThLdYAFhRC7pIELBVt/EUuGL5lDzcIHYA= bob@laptop\n")?;
+    temp.child(".ssh/id_ed25519.pub").write_str("ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIHsKLqeplhpW+uObz5dvMgjz1OxfM/XXUB+VHtZ6isGN alice@rust\n")?;
+    temp.child(".ssh/id_ed25519_sk.pub").write_str("sk-ssh-ed25519@openssh.com AAAAGnNrLXNzaC1lZDI1NTE5QG9wZW5zc2guY29tAAAAIAABAgMEBQYHCAkKCwwNDg8QERITFBUWFxgZGhscHR4fAAAABHNzaDo= carol@yubikey\n")?;
+    let output = temp.child("output.pdf");
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("--output")
+        .arg(output.path())
+        .arg("--ssh-keys")
+        .arg("-vvv")
+        .arg(input.path())
+        .env("HOME", temp.path());
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "Unsupported SSH key type: ecdsa-sha2-nistp256",
+        ))
+        .stderr(predicate::str::contains(
+            "Unsupported SSH key type: sk-ssh-ed25519@openssh.com",
+        ))
+        .stderr(predicate::str::contains(
+            "Encrypting plaintext to 1 recipient(s)",
+        ));
+
+    output.assert(predicate::path::is_file());
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn stub_plugin_path() -> String {
+    let data_dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data");
+    format!(
+        "{}:{}",
+        data_dir.display(),
+        std::env::var("PATH").unwrap_or_default()
+    )
+}
+
+#[cfg(unix)]
+#[test]
+fn test_plugin_recipient() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let input = temp.child("sample.txt");
+    input.write_str("Hello")?;
+    let output = temp.child("output.pdf");
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("--output")
+        .arg(output.path())
+        .arg("--recipient")
+        .arg("age1stub1wpshqetj94skwefqw3jhxapqwd682c3qwpk82emfdcsxketeyyssclvhyh")
+        .arg(input.path())
+        .env("PATH", stub_plugin_path());
+    cmd.assert().success();
+
+    output.assert(predicate::path::is_file());
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_plugin_error() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let input = temp.child("sample.txt");
+    input.write_str("Hello")?;
+    let output = temp.child("output.pdf");
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("--output")
+        .arg(output.path())
+        .arg("--recipient")
+        .arg("age1stub1wpshqetj94skwefqw3jhxapqwd682c3qwpk82emfdcsxketeyyssclvhyh")
+        .arg(input.path())
+        .env("PATH", stub_plugin_path())
+        .env("STUB_PLUGIN_ERROR", "no hardware key found");
+    cmd.assert()
+        .failure()
+        .code(exitcode::PROTOCOL)
+        .stderr(predicate::str::contains("no hardware key found"));
+
+    output.assert(predicate::path::missing());
+
+    Ok(())
+}
+
+#[test]
+fn test_missing_plugin() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let input = temp.child("sample.txt");
+    input.write_str("Hello")?;
+    let output = temp.child("output.pdf");
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("--output")
+        .arg(output.path())
+        .arg("--recipient")
+        .arg("age1missing1wpshqetj94skwefqw3jhxapqwd682c3qwpk82emfdcsxketeyyssacd2zz")
+        .arg(input.path());
+    cmd.assert()
+        .failure()
+        .code(exitcode::UNAVAILABLE)
+        .stderr(predicate::str::contains("age-plugin-missing"));
+
+    output.assert(predicate::path::missing());
+
+    Ok(())
+}
+
+#[test]
+fn test_weak_passphrase() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let input = temp.child("sample.txt");
+    input.write_str("Hello")?;
+    let output = temp.child("output.pdf");
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("--output")
+        .arg(output.path())
+        .arg(input.path())
+        .env("PAPERAGE_PASSPHRASE", "secret");
+    cmd.assert()
+        .failure()
+        .code(exitcode::DATAERR)
+        .stderr(predicate::str::contains("Passphrase is too weak"));
+
+    output.assert(predicate::path::missing());
+
+    Ok(())
+}
+
+#[test]
+fn test_allow_weak_passphrase() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let input = temp.child("sample.txt");
+    input.write_str("Hello")?;
+    let output = temp.child("output.pdf");
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("--output")
+        .arg(output.path())
+        .arg("--allow-weak-passphrase")
+        .arg(input.path())
+        .env("PAPERAGE_PASSPHRASE", "secret");
+    cmd.assert().success();
+
+    output.assert(predicate::path::is_file());
+
+    Ok(())
+}
+
+#[test]
+fn test_generate_passphrase() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let input = temp.child("sample.txt");
+    input.write_str("Hello")?;
+    let output = temp.child("output.pdf");
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    let passphrase = temp.child("passphrase.pdf");
+    cmd.arg("--output")
+        .arg(output.path())
+        .arg("--generate-passphrase=7")
+        .arg("--passphrase-output")
+        .arg(passphrase.path())
+        .arg(input.path());
+    cmd.assert().success();
+
+    output.assert(predicate::path::is_file());
+    passphrase.assert(predicate::path::is_file());
+
+    // The passphrase is never written to the sheet itself
+    let mut cmd = Command::cargo_bin("paper-age")?;
+    cmd.current_dir(temp.path())
+        .arg("--output")
+        .arg("other.pdf")
+        .arg("--generate-passphrase")
+        .arg("--passphrase-output")
+        .arg("./other.pdf")
+        .arg(input.path());
+    cmd.assert().failure().code(exitcode::USAGE);
+    temp.child("other.pdf").assert(predicate::path::missing());
+
+    Ok(())
+}
+
+#[test]
+fn test_work_factor() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let input = temp.child("sample.txt");
+    input.write_str("Hello")?;
+    let output = temp.child("output.pdf");
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("--output")
+        .arg(output.path())
+        .arg("--work-factor")
+        .arg("12")
+        .arg("-vvv")
+        .arg(input.path())
+        .env("PAPERAGE_PASSPHRASE", PASSPHRASE);
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains("scrypt work factor: 2^12"));
+
+    output.assert(predicate::path::is_file());
+
+    Ok(())
+}
+
+#[test]
+fn test_work_factor_out_of_range() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let input = temp.child("sample.txt");
+    input.write_str("Hello")?;
+    let output = temp.child("output.pdf");
+
+    for work_factor in ["1", "31", "40"] {
+        let mut cmd = Command::cargo_bin("paper-age")?;
+        cmd.arg("--output")
+            .arg(output.path())
+            .arg("--work-factor")
+            .arg(work_factor)
+            .arg(input.path())
+            .env("PAPERAGE_PASSPHRASE", PASSPHRASE);
+        cmd.assert()
+            .failure()
+            .code(2)
+            .stderr(predicate::str::contains("10..=30"));
+    }
+
+    output.assert(predicate::path::missing());
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_passphrase_file() -> Result<(), Box<dyn std::error::Error>> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp = assert_fs::TempDir::new().unwrap();
+    let input = temp.child("sample.txt");
+    input.write_str("Hello")?;
+    let passphrase = temp.child("passphrase.txt");
+    passphrase.write_str(format!("{PASSPHRASE}\n").as_str())?;
+    std::fs::set_permissions(passphrase.path(), std::fs::Permissions::from_mode(0o600))?;
+    let output = temp.child("output.pdf");
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("--output")
+        .arg(output.path())
+        .arg("--passphrase-file")
+        .arg(passphrase.path())
+        .arg(input.path())
+        .env_remove("PAPERAGE_PASSPHRASE");
+    cmd.assert().success();
+
+    output.assert(predicate::path::is_file());
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_passphrase_file_permissions() -> Result<(), Box<dyn std::error::Error>> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp = assert_fs::TempDir::new().unwrap();
+    let input = temp.child("sample.txt");
+    input.write_str("Hello")?;
+    let passphrase = temp.child("passphrase.txt");
+    passphrase.write_str(PASSPHRASE)?;
+    std::fs::set_permissions(passphrase.path(), std::fs::Permissions::from_mode(0o644))?;
+    let output = temp.child("output.pdf");
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("--output")
+        .arg(output.path())
+        .arg("--passphrase-file")
+        .arg(passphrase.path())
+        .arg(input.path());
+    cmd.assert()
+        .failure()
+        .code(exitcode::NOPERM)
+        .stderr(predicate::str::contains("accessible by other users"));
+
+    output.assert(predicate::path::missing());
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_passphrase_fd() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let input = temp.child("sample.txt");
+    input.write_str("Hello")?;
+    let output = temp.child("output.pdf");
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("--output")
+        .arg(output.path())
+        .arg("--passphrase-fd")
+        .arg("0")
+        .arg(input.path())
+        .env_remove("PAPERAGE_PASSPHRASE")
+        .write_stdin(format!("{PASSPHRASE}\n"));
+    cmd.assert().success();
+
+    output.assert(predicate::path::is_file());
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_passphrase_fd_stdout() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let input = temp.child("sample.txt");
+    input.write_str("Hello")?;
+
+    for fd in ["1", "2"] {
+        let mut cmd = Command::cargo_bin("paper-age")?;
+        cmd.arg("--output")
+            .arg("-")
+            .arg("--passphrase-fd")
+            .arg(fd)
+            .arg(input.path())
+            .env_remove("PAPERAGE_PASSPHRASE");
+        cmd.assert()
+            .failure()
+            .code(64)
+            .stderr(predicate::str::contains(
+                "Can't read the passphrase from STDOUT or STDERR",
+            ));
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_pinentry() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let input = temp.child("sample.txt");
+    input.write_str("Hello")?;
+    let output = temp.child("output.pdf");
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("--output")
+        .arg(output.path())
+        .arg("--pinentry")
+        .arg(data_path("pinentry-stub"))
+        .arg(input.path())
+        .env_remove("PAPERAGE_PASSPHRASE")
+        .env("STUB_PIN", PASSPHRASE);
+    cmd.assert().success();
+
+    output.assert(predicate::path::is_file());
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_pinentry_cancelled() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let input = temp.child("sample.txt");
+    input.write_str("Hello")?;
+    let output = temp.child("output.pdf");
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("--output")
+        .arg(output.path())
+        .arg("--pinentry")
+        .arg(data_path("pinentry-stub"))
+        .arg(input.path())
+        .env_remove("PAPERAGE_PASSPHRASE")
+        .env_remove("STUB_PIN");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("Passphrase entry was cancelled"));
+
+    output.assert(predicate::path::missing());
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_askpass() -> Result<(), Box<dyn std::error::Error>> {
+    // Forced, preferred with a display, or picked because STDIN isn't a terminal
+    for (require, display) in [("force", None), ("prefer", Some(":0")), ("", None)] {
+        let (temp, mut cmd) = sheet_command(&["--work-factor=10"], b"Hello")?;
+
+        cmd.env_remove("PAPERAGE_PASSPHRASE")
+            .env_remove("DISPLAY")
+            .env_remove("WAYLAND_DISPLAY")
+            .env("SSH_ASKPASS", data_path("askpass-stub"))
+            .env("SSH_ASKPASS_REQUIRE", require)
+            .env("STUB_PIN", PASSPHRASE)
+            .write_stdin("");
+        if let Some(display) = display {
+            cmd.env("DISPLAY", display);
+        }
+        cmd.assert().success();
+
+        let [ciphertext] = &sheet_ciphertexts(&temp)?[..] else {
+            panic!("Expected one sheet");
+        };
+        assert_eq!(decrypt_sheet(ciphertext, PASSPHRASE, None)?, b"Hello");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_compress() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let input = temp.child("sample.txt");
+    input.write_str("recovery-code-0000-0000\n".repeat(200).as_str())?;
+    let output = temp.child("output.pdf");
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("--output")
+        .arg(output.path())
+        .arg("--compress")
+        .arg("-vv")
+        .arg(input.path())
+        .env("PAPERAGE_PASSPHRASE", PASSPHRASE);
+    cmd.assert().success().stderr(predicate::str::contains(
+        "Compressed the plaintext from 4800",
+    ));
+
+    output.assert(predicate::path::is_file());
+
+    Ok(())
+}
+
+#[test]
+fn test_qr_encoding() -> Result<(), Box<dyn std::error::Error>> {
+    // Too large for an armored QR code, but fits in the compact encodings
+    let plaintext = "x".repeat(2300);
+
+    let (_temp, mut cmd) = sheet_command(&["--work-factor=10"], plaintext.as_bytes())?;
+    cmd.assert().failure().code(exitcode::DATAERR);
+
+    for encoding in ["binary", "base45"] {
+        let (temp, mut cmd) = sheet_command(
+            &["--work-factor=10", "--qr-encoding", encoding, "-vv"],
+            plaintext.as_bytes(),
+        )?;
+        cmd.assert()
+            .success()
+            .stderr(predicate::str::is_match(format!(
+                r"QR code payload: \d+ bytes \({encoding}\)"
+            ))?);
+
+        let [ciphertext] = &sheet_ciphertexts(&temp)?[..] else {
+            panic!("Expected one sheet");
+        };
+        assert_eq!(
+            decrypt_sheet(ciphertext, PASSPHRASE, None)?,
+            plaintext.as_bytes()
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_shares() -> Result<(), Box<dyn std::error::Error>> {
+    let (temp, mut cmd) = sheet_command(
+        &[
+            "--shares=3",
+            "--threshold=2",
+            "--custodian=Alice",
+            "--custodian=Bob",
+            "--work-factor=10",
+            "-vv",
+        ],
+        b"Hello",
+    )?;
+    cmd.assert().success().stderr(predicate::str::contains(
+        "Split the plaintext into 3 shares",
+    ));
+
+    let ciphertexts = sheet_ciphertexts(&temp)?;
+    assert_eq!(ciphertexts.len(), 3);
+
+    // Any two of the shares recover the secret
+    let first = temp.child("share1");
+    first.write_binary(&decrypt_sheet(&ciphertexts[0], PASSPHRASE, None)?)?;
+    let third = temp.child("share3");
+    third.write_binary(&decrypt_sheet(&ciphertexts[2], PASSPHRASE, None)?)?;
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("combine").arg(first.path()).arg(third.path());
+    cmd.assert().success().stdout("Hello");
+
+    Ok(())
+}
+
+#[test]
+fn test_threshold_larger_than_shares() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("--shares=2").arg("--threshold=3");
+    cmd.assert().failure().code(exitcode::USAGE);
+
+    Ok(())
+}
+
+/// Share with a constant polynomial, i.e. the data is the secret itself
+fn constant_share(set_id: &[u8; 4], index: u8, secret: &[u8]) -> Vec<u8> {
+    let mut share = b"\x00PAS\x01".to_vec();
+    share.extend_from_slice(set_id);
+    share.extend_from_slice(&[2, 3, index]);
+    share.extend_from_slice(secret);
+    share
+}
+
+#[test]
+fn test_combine() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let first = temp.child("share1");
+    first.write_binary(&constant_share(b"abcd", 1, b"Hello"))?;
+    let third = temp.child("share3");
+    third.write_binary(&constant_share(b"abcd", 3, b"Hello"))?;
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("combine").arg(first.path()).arg(third.path());
+    cmd.assert().success().stdout("Hello");
+
+    Ok(())
+}
+
+#[test]
+fn test_combine_different_sets() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let first = temp.child("share1");
+    first.write_binary(&constant_share(b"abcd", 1, b"Hello"))?;
+    let second = temp.child("share2");
+    second.write_binary(&constant_share(b"efgh", 2, b"Hello"))?;
+    let output = temp.child("secret.txt");
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("combine")
+        .arg("--output")
+        .arg(output.path())
+        .arg(first.path())
+        .arg(second.path());
+    cmd.assert()
+        .failure()
+        .code(exitcode::DATAERR)
+        .stderr(predicate::str::contains("Shares from different sets"));
+
+    output.assert(predicate::path::missing());
+
+    Ok(())
+}
+
+fn data_path(name: &str) -> std::path::PathBuf {
+    std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/data")
+        .join(name)
+}
+
+/// Temporary directory with `input` in `sample.txt`, and a command that
+/// encrypts it to `output.pdf` and writes the ciphertexts to `output.age`.
+/// `args` go first, so that they can start with a subcommand.
+fn sheet_command(
+    args: &[&str],
+    input: &[u8],
+) -> Result<(assert_fs::TempDir, Command), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new()?;
+    let sample = temp.child("sample.txt");
+    sample.write_binary(input)?;
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.args(args)
+        .arg("--output")
+        .arg(temp.child("output.pdf").path())
+        .arg("--armor-output")
+        .arg(temp.child("output.age").path())
+        .arg(sample.path())
+        .env("PAPERAGE_PASSPHRASE", PASSPHRASE);
+
+    Ok((temp, cmd))
+}
+
+/// The ciphertexts of the sheets in `output.pdf`, in order
+fn sheet_ciphertexts(temp: &assert_fs::TempDir) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    const END: &str = "-----END AGE ENCRYPTED FILE-----\n";
+
+    temp.child("output.pdf").assert(predicate::path::is_file());
+
+    let armored = std::fs::read_to_string(temp.child("output.age").path())?;
+    Ok(armored.split_inclusive(END).map(String::from).collect())
+}
+
+/// Decrypt the ciphertext of a sheet with paper-age decrypt
+fn decrypt_sheet(
+    ciphertext: &str,
+    passphrase: &str,
+    identity: Option<&std::path::Path>,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("decrypt")
+        .write_stdin(ciphertext)
+        .env("PAPERAGE_PASSPHRASE", passphrase);
+    if let Some(identity) = identity {
+        cmd.arg("--identity").arg(identity);
+    }
+
+    Ok(cmd.assert().success().get_output().stdout.clone())
+}
+
+#[test]
+fn test_decrypt() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("decrypt")
+        .arg(data_path("passphrase.age"))
+        .env("PAPERAGE_PASSPHRASE", PASSPHRASE);
+    cmd.assert().success().stdout("Hello from the sheet\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_decrypt_paranoid() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("decrypt")
+        .arg("--paranoid")
+        .arg(data_path("passphrase.age"))
+        .env("PAPERAGE_PASSPHRASE", PASSPHRASE);
+    cmd.assert().success().stdout("Hello from the sheet\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_decrypt_fingerprint() -> Result<(), Box<dyn std::error::Error>> {
+    let fingerprint = |input: String| -> Result<String, Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin("paper-age")?;
+        cmd.arg("decrypt")
+            .arg("-vv")
+            .write_stdin(input)
+            .env("PAPERAGE_PASSPHRASE", PASSPHRASE);
+        let output = cmd.assert().success().get_output().clone();
+
+        let stderr = String::from_utf8(output.stderr)?;
+        let line = stderr
+            .lines()
+            .find(|line| line.contains("Ciphertext fingerprint: "))
+            .ok_or("No fingerprint in the output")?;
+        Ok(line
+            .split("Ciphertext fingerprint: ")
+            .nth(1)
+            .unwrap()
+            .to_string())
+    };
+
+    let original = std::fs::read_to_string(data_path("passphrase.age"))?;
+    let retyped = original
+        .lines()
+        .map(|line| format!("    {line}\r\n"))
+        .collect::<String>();
+
+    assert_eq!(fingerprint(original)?, fingerprint(retyped)?);
+
+    Ok(())
+}
+
+#[test]
+fn test_sign_with() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let output = temp.child("output.pdf");
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("--output")
+        .arg(output.path())
+        .arg("--sign-with")
+        .arg(data_path("signing_key"))
+        .arg("-vv")
+        .write_stdin("Hello")
+        .env("PAPERAGE_PASSPHRASE", PASSPHRASE);
+    cmd.assert().success().stderr(predicate::str::contains(
+        "Signing with SHA256:Czcx0aE58iBMew4xlwg51bQSlK8Exzk/NXuKtJsZ5xw",
+    ));
+
+    output.assert(predicate::path::is_file());
+
+    Ok(())
+}
+
+#[test]
+fn test_decrypt_verify_signature() -> Result<(), Box<dyn std::error::Error>> {
+    // Signed with ssh-keygen -Y sign -n paper-age
+    let mut cmd = Command::cargo_bin("paper-age")?;
+    cmd.arg("decrypt")
+        .arg("--verify-with")
+        .arg(data_path("signing_key.pub"))
+        .arg("--signature")
+        .arg(data_path("passphrase.age.sig"))
+        .arg(data_path("passphrase.age"))
+        .env("PAPERAGE_PASSPHRASE", PASSPHRASE);
+    cmd.assert().success().stdout("Hello from the sheet\n");
+
+    // Typed below the ciphertext
+    let typed = format!(
+        "{}\n{}",
+        std::fs::read_to_string(data_path("passphrase.age"))?,
+        std::fs::read_to_string(data_path("passphrase.age.sig"))?
+    );
+    let mut cmd = Command::cargo_bin("paper-age")?;
+    cmd.arg("decrypt")
+        .arg("--verify-with")
+        .arg(data_path("signing_key.pub"))
+        .write_stdin(typed)
+        .env("PAPERAGE_PASSPHRASE", PASSPHRASE);
+    cmd.assert().success().stdout("Hello from the sheet\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_decrypt_untrusted_signature() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("paper-age")?;
+    cmd.arg("decrypt")
+        .arg("--verify-with")
+        .arg(data_path("untrusted_key.pub"))
+        .arg("--signature")
+        .arg(data_path("passphrase.age.sig"))
+        .arg(data_path("passphrase.age"))
+        .env("PAPERAGE_PASSPHRASE", PASSPHRASE);
+    cmd.assert()
+        .failure()
+        .code(exitcode::DATAERR)
+        .stdout("")
+        .stderr(predicate::str::contains(
+            "wasn't signed with the trusted key",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn test_decrypt_missing_signature() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("paper-age")?;
+    cmd.arg("decrypt")
+        .arg("--verify-with")
+        .arg(data_path("signing_key.pub"))
+        .arg(data_path("passphrase.age"))
+        .env("PAPERAGE_PASSPHRASE", PASSPHRASE);
+    cmd.assert()
+        .failure()
+        .code(exitcode::DATAERR)
+        .stderr(predicate::str::contains("No signature found"));
+
+    Ok(())
+}
+
+#[test]
+fn test_two_factor() -> Result<(), Box<dyn std::error::Error>> {
+    let (temp, mut cmd) = sheet_command(
+        &[
+            "--second-factor",
+            "age1g3jnt5c79rs5nulvthlswd4x0psepldrthmsp6t6y665ff5l7e4q38r8sy",
+            "--work-factor=10",
+            "-vv",
+        ],
+        b"Hello",
+    )?;
+    cmd.assert().success().stderr(predicate::str::contains(
+        "Two factors: the passphrase and an identity for one of 1 recipient(s)",
+    ));
+
+    let [ciphertext] = &sheet_ciphertexts(&temp)?[..] else {
+        panic!("Expected one sheet");
+    };
+    assert_eq!(
+        decrypt_sheet(ciphertext, PASSPHRASE, Some(&data_path("identity.txt")))?,
+        b"Hello"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_decrypt_two_factor() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("paper-age")?;
+    cmd.arg("decrypt")
+        .arg("--identity")
+        .arg(data_path("identity.txt"))
+        .arg(data_path("two_factor.age"))
+        .env("PAPERAGE_PASSPHRASE", PASSPHRASE);
+    cmd.assert().success().stdout("Hello from both factors\n");
+
+    // Only the passphrase layer
+    let mut cmd = Command::cargo_bin("paper-age")?;
+    cmd.arg("decrypt")
+        .arg("-v")
+        .arg(data_path("two_factor.age"))
+        .env("PAPERAGE_PASSPHRASE", PASSPHRASE);
+    cmd.assert()
+        .success()
+        .stdout(predicate::function(|stdout: &[u8]| {
+            stdout.starts_with(b"age-encryption.org/v1\n")
+        }))
+        .stderr(predicate::str::contains("The sheet has a second factor"));
+
+    Ok(())
+}
+
+#[test]
+fn test_decrypt_typed_text() -> Result<(), Box<dyn std::error::Error>> {
+    // Indented with CRLF line endings, as pasted from an email
+    let typed = std::fs::read_to_string(data_path("passphrase.age"))?
+        .lines()
+        .map(|line| format!("  {line} \r\n"))
+        .collect::<String>();
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("decrypt")
+        .write_stdin(typed)
+        .env("PAPERAGE_PASSPHRASE", PASSPHRASE);
+    cmd.assert().success().stdout("Hello from the sheet\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_decrypt_wrong_passphrase() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("decrypt")
+        .arg(data_path("passphrase.age"))
+        .env("PAPERAGE_PASSPHRASE", "wrong passphrase");
+    cmd.assert()
+        .failure()
+        .code(exitcode::NOPERM)
+        .stderr(predicate::str::contains("Decryption failed"));
+
+    Ok(())
+}
+
+#[test]
+fn test_decrypt_identity() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("decrypt")
+        .arg("--identity")
+        .arg(data_path("identity.txt"))
+        .arg(data_path("recipient.age"));
+    cmd.assert().success().stdout("Hello recipient\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_decrypt_output_exists() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let output = temp.child("secret.txt");
+    output.write_str("Existing")?;
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("decrypt")
+        .arg("--output")
+        .arg(output.path())
+        .arg(data_path("passphrase.age"))
+        .env("PAPERAGE_PASSPHRASE", PASSPHRASE);
+    cmd.assert().failure().code(exitcode::CANTCREAT);
+
+    output.assert("Existing");
+
+    let mut cmd = Command::cargo_bin("paper-age")?;
+    cmd.arg("decrypt")
+        .arg("--force")
+        .arg("--output")
+        .arg(output.path())
+        .arg(data_path("passphrase.age"))
+        .env("PAPERAGE_PASSPHRASE", PASSPHRASE);
+    cmd.assert().success();
+
+    output.assert("Hello from the sheet\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_armor_output() -> Result<(), Box<dyn std::error::Error>> {
+    let (temp, mut cmd) = sheet_command(&["--work-factor=10"], b"Hello")?;
+    cmd.assert().success();
+
+    let [ciphertext] = &sheet_ciphertexts(&temp)?[..] else {
+        panic!("Expected one sheet");
+    };
+    assert_eq!(decrypt_sheet(ciphertext, PASSPHRASE, None)?, b"Hello");
+
+    // The file can be decrypted as it is
+
+    let mut cmd = Command::cargo_bin("paper-age")?;
+    cmd.arg("decrypt")
+        .arg(temp.child("output.age").path())
+        .env("PAPERAGE_PASSPHRASE", PASSPHRASE);
+    cmd.assert().success().stdout("Hello");
+
+    // Not over the sheet itself
+    let mut cmd = Command::cargo_bin("paper-age")?;
+    cmd.arg("--output")
+        .arg(temp.child("other.pdf").path())
+        .arg("--armor-output")
+        .arg(temp.child("other.pdf").path())
+        .arg(temp.child("sample.txt").path())
+        .env("PAPERAGE_PASSPHRASE", PASSPHRASE);
+    cmd.assert().failure().code(exitcode::USAGE);
+
+    Ok(())
+}
+
+#[test]
+fn test_rewrap() -> Result<(), Box<dyn std::error::Error>> {
+    let (temp, mut cmd) = sheet_command(
+        &[
+            "rewrap",
+            "--title",
+            "Rotated",
+            "--recipient",
+            "age1g3jnt5c79rs5nulvthlswd4x0psepldrthmsp6t6y665ff5l7e4q38r8sy",
+            "-vv",
+        ],
+        &std::fs::read(data_path("passphrase.age"))?,
+    )?;
+
+    cmd.env_remove("PAPERAGE_PASSPHRASE")
+        .env("PAPERAGE_OLD_PASSPHRASE", PASSPHRASE);
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains("Decrypted the old sheet"));
+
+    let [ciphertext] = &sheet_ciphertexts(&temp)?[..] else {
+        panic!("Expected one sheet");
+    };
+    assert_eq!(
+        decrypt_sheet(ciphertext, PASSPHRASE, Some(&data_path("identity.txt")))?,
+        b"Hello from the sheet\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_rewrap_identity() -> Result<(), Box<dyn std::error::Error>> {
+    let old_identity = data_path("identity.txt");
+    let (temp, mut cmd) = sheet_command(
+        &[
+            "rewrap",
+            "--title",
+            "Rotated",
+            "--old-identity",
+            old_identity.to_str().unwrap(),
+            "--work-factor=10",
+        ],
+        &std::fs::read(data_path("recipient.age"))?,
+    )?;
+    cmd.assert().success();
+
+    let [ciphertext] = &sheet_ciphertexts(&temp)?[..] else {
+        panic!("Expected one sheet");
+    };
+    assert_eq!(
+        decrypt_sheet(ciphertext, PASSPHRASE, None)?,
+        b"Hello recipient\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_rewrap_wrong_passphrase() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let output = temp.child("output.pdf");
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("rewrap")
+        .arg("--title")
+        .arg("Rotated")
+        .arg("--output")
+        .arg(output.path())
+        .arg(data_path("passphrase.age"))
+        .env("PAPERAGE_OLD_PASSPHRASE", "wrong passphrase")
+        .env("PAPERAGE_PASSPHRASE", PASSPHRASE);
+    cmd.assert()
+        .failure()
+        .code(exitcode::NOPERM)
+        .stderr(predicate::str::contains("Decryption failed"));
+
+    output.assert(predicate::path::missing());
+
+    Ok(())
+}
+
+#[test]
+fn test_keygen() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let output = temp.child("output.pdf");
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("keygen")
+        .arg("--output")
+        .arg(output.path())
+        .arg("--work-factor")
+        .arg("10")
+        .env("PAPERAGE_PASSPHRASE", PASSPHRASE);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::is_match("^age1[0-9a-z]{58}\n$")?);
+
+    output.assert(predicate::path::is_file());
+
+    Ok(())
+}
+
+#[test]
+fn test_keygen_stdout() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("keygen")
+        .arg("--output")
+        .arg("-")
+        .env("PAPERAGE_PASSPHRASE", PASSPHRASE);
+    cmd.assert().failure().code(exitcode::USAGE);
+
+    Ok(())
+}
+
+#[test]
+fn test_identity_file() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let output = temp.child("output.pdf");
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("--output")
+        .arg(output.path())
+        .arg("--work-factor")
+        .arg("10")
+        .arg("-vv")
+        .arg(data_path("identity.txt"))
+        .env("PAPERAGE_PASSPHRASE", PASSPHRASE);
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "The input is an age identity file with 1 identities",
+        ))
+        .stderr(predicate::str::contains("Compacted the identity file"));
+
+    output.assert(predicate::path::is_file());
+
+    Ok(())
+}
+
+#[test]
+fn test_openpgp() -> Result<(), Box<dyn std::error::Error>> {
+    let (temp, mut cmd) = sheet_command(
+        &["--openpgp", "--work-factor=10", "-vv"],
+        &std::fs::read(data_path("openpgp_secret.asc"))?,
+    )?;
+    cmd.assert().success().stderr(predicate::str::contains(
+        "Minimized the OpenPGP secret key from 744 to 125 bytes",
+    ));
+
+    let [ciphertext] = &sheet_ciphertexts(&temp)?[..] else {
+        panic!("Expected one sheet");
+    };
+    assert_eq!(
+        decrypt_sheet(ciphertext, PASSPHRASE, None)?,
+        std::fs::read(data_path("openpgp_minimized.bin"))?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_openpgp_hint() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let output = temp.child("output.pdf");
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("--output")
+        .arg(output.path())
+        .arg("--work-factor")
+        .arg("10")
+        .arg("-v")
+        .arg(data_path("openpgp_secret.asc"))
+        .env("PAPERAGE_PASSPHRASE", PASSPHRASE);
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains("use --openpgp"));
+
+    Ok(())
+}
+
+#[test]
+fn test_restore_openpgp() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("restore-openpgp")
+        .arg("--public-key")
+        .arg(data_path("openpgp_public.asc"))
+        .arg(data_path("openpgp_minimized.bin"));
+    cmd.assert()
+        .success()
+        // A new format secret key packet
+        .stdout(predicate::function(|stdout: &[u8]| {
+            stdout.first() == Some(&0xc5)
+        }));
+
+    let mut cmd = Command::cargo_bin("paper-age")?;
+    cmd.arg("restore-openpgp")
+        .arg("--public-key")
+        .arg(data_path("openpgp_rsa_public.gpg"))
+        .arg(data_path("openpgp_minimized.bin"));
+    cmd.assert()
+        .failure()
+        .code(exitcode::DATAERR)
+        .stderr(predicate::str::contains("No secret key material"));
+
+    Ok(())
+}
+
+#[test]
+fn test_bip39_entropy() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let output = temp.child("output.pdf");
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("--output")
+        .arg(output.path())
+        .arg("--bip39-entropy")
+        .arg("--work-factor")
+        .arg("10")
+        .arg("-vv")
+        .write_stdin(
+            "legal winner thank year wave sausage worth useful legal winner thank yellow\n",
+        )
+        .env("PAPERAGE_PASSPHRASE", PASSPHRASE);
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "The input is a BIP39 mnemonic with a valid checksum (128 bits of entropy)",
+        ))
+        .stderr(predicate::str::contains(
+            "Storing the entropy instead of the words (20 instead of 76 bytes)",
+        ));
+
+    output.assert(predicate::path::is_file());
+
+    Ok(())
+}
+
+#[test]
+fn test_bip39_invalid_checksum() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let output = temp.child("output.pdf");
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("--output")
+        .arg(output.path())
+        .arg("--bip39-entropy")
+        .write_stdin("legal winner thank year wave sausage worth useful legal winner thank thank\n")
+        .env("PAPERAGE_PASSPHRASE", PASSPHRASE);
+    cmd.assert()
+        .failure()
+        .code(exitcode::DATAERR)
+        .stderr(predicate::str::contains(
+            "Refusing to print a mnemonic with an invalid checksum",
+        ));
+
+    output.assert(predicate::path::missing());
+
+    Ok(())
+}
+
+#[test]
+fn test_bip39_invalid_checksum_without_entropy() -> Result<(), Box<dyn std::error::Error>> {
+    // A valid 24 word mnemonic with the last word replaced
+    let mnemonic = "legal winner thank year wave sausage worth useful legal winner thank year wave sausage worth useful legal winner thank year wave sausage worth thank\n";
+
+    let (temp, mut cmd) = sheet_command(&[], mnemonic.as_bytes())?;
+    cmd.assert()
+        .failure()
+        .code(exitcode::DATAERR)
+        .stderr(predicate::str::contains(
+            "Refusing to print a mnemonic with an invalid checksum",
+        ));
+    temp.child("output.pdf").assert(predicate::path::missing());
+
+    let (temp, mut cmd) = sheet_command(
+        &["--allow-invalid-mnemonic", "--work-factor=10"],
+        mnemonic.as_bytes(),
+    )?;
+    cmd.assert().success();
+
+    let [ciphertext] = &sheet_ciphertexts(&temp)?[..] else {
+        panic!("Expected one sheet");
+    };
+    assert_eq!(
+        decrypt_sheet(ciphertext, PASSPHRASE, None)?,
+        mnemonic.as_bytes()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_decrypt_bip39_entropy() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("decrypt")
+        .arg(data_path("bip39.age"))
+        .env("PAPERAGE_PASSPHRASE", PASSPHRASE);
+    cmd.assert()
+        .success()
+        .stdout("legal winner thank year wave sausage worth useful legal winner thank yellow\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_key_sheet() -> Result<(), Box<dyn std::error::Error>> {
+    let dump = "INSERT INTO secrets VALUES ('hunter2');\n".repeat(1000);
+    let encrypted = assert_fs::NamedTempFile::new("dump.sql.age")?;
+    let (temp, mut cmd) = sheet_command(
+        &[
+            "--key-sheet",
+            encrypted.path().to_str().unwrap(),
+            "--work-factor=10",
+            "-vv",
+        ],
+        dump.as_bytes(),
+    )?;
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains("Encrypted the input to"))
+        .stderr(predicate::str::contains("SHA-256"));
+
+    // The identity on the sheet decrypts the file
+    let [ciphertext] = &sheet_ciphertexts(&temp)?[..] else {
+        panic!("Expected one sheet");
+    };
+    let identity = temp.child("key.txt");
+    identity.write_binary(&decrypt_sheet(ciphertext, PASSPHRASE, None)?)?;
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("decrypt")
+        .arg("--identity")
+        .arg(identity.path())
+        .arg(encrypted.path());
+    cmd.assert().success().stdout(dump);
+
+    // The encrypted file isn't overwritten
+    let mut cmd = Command::cargo_bin("paper-age")?;
+    cmd.arg("--output")
+        .arg(temp.child("other.pdf").path())
+        .arg("--key-sheet")
+        .arg(encrypted.path())
+        .arg(temp.child("sample.txt").path())
+        .env("PAPERAGE_PASSPHRASE", PASSPHRASE);
+    cmd.assert().failure().code(exitcode::CANTCREAT);
+
+    Ok(())
+}
+
+#[test]
+fn test_key_sheet_weak_passphrase() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let input = temp.child("dump.sql");
+    input.write_str("INSERT INTO secrets VALUES ('hunter2');\n")?;
+    let encrypted = temp.child("dump.sql.age");
+    encrypted.write_str("existing")?;
+    let output = temp.child("output.pdf");
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("--output")
+        .arg(output.path())
+        .arg("--key-sheet")
+        .arg(encrypted.path())
+        .arg("--force")
+        .arg(input.path())
+        .env("PAPERAGE_PASSPHRASE", "secret");
+    cmd.assert().failure().code(exitcode::DATAERR);
+
+    // The passphrase is checked before anything is written
+    encrypted.assert("existing");
+    output.assert(predicate::path::missing());
+    assert_eq!(std::fs::read_dir(temp.path())?.count(), 2);
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_holders() -> Result<(), Box<dyn std::error::Error>> {
+    let (temp, mut cmd) = sheet_command(
+        &[
+            "--holder",
+            "Alice",
+            "--holder",
+            "Bob",
+            "--work-factor=10",
+            "-vv",
+        ],
+        b"Hello",
+    )?;
+
+    // The stub gives both holders the same passphrase
+    cmd.env("SSH_ASKPASS", data_path("askpass-stub"))
+        .env("SSH_ASKPASS_REQUIRE", "force")
+        .env("STUB_PIN", PASSPHRASE);
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains("Ignoring PAPERAGE_PASSPHRASE"))
+        .stderr(predicate::str::contains(
+            "One sheet for each of the 2 holders",
+        ))
+        .stderr(predicate::str::contains(
+            "Alice and Bob have the same passphrase",
+        ));
+
+    let ciphertexts = sheet_ciphertexts(&temp)?;
+    assert_eq!(ciphertexts.len(), 2);
+    for ciphertext in &ciphertexts {
+        assert_eq!(decrypt_sheet(ciphertext, PASSPHRASE, None)?, b"Hello");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_holders_passphrase_file() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let input = temp.child("sample.txt");
+    input.write_str("Hello")?;
+    let passphrase = temp.child("passphrase.txt");
+    passphrase.write_str(PASSPHRASE)?;
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("--output")
+        .arg(temp.child("output.pdf").path())
+        .arg("--holder")
+        .arg("Alice")
+        .arg("--passphrase-file")
+        .arg(passphrase.path())
+        .arg(input.path());
+    cmd.assert().failure().code(exitcode::USAGE);
+
+    Ok(())
+}
+
+#[test]
+fn test_break_glass() -> Result<(), Box<dyn std::error::Error>> {
+    let (temp, mut cmd) = sheet_command(
+        &["--break-glass-label", "Master", "--work-factor=10", "-vv"],
+        b"recovery email: hunter2",
+    )?;
+    let break_glass = temp.child("break-glass.txt");
+    break_glass.write_str("master password: correct horse battery staple")?;
+
+    cmd.arg("--break-glass").arg(break_glass.path()).env(
+        "PAPERAGE_BREAK_GLASS_PASSPHRASE",
+        "grumpy-walrus-marmalade-17",
+    );
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "Everyday ciphertext fingerprint: ",
+        ))
+        .stderr(predicate::str::contains("Master ciphertext fingerprint: "));
+
+    // Each tier is decrypted with its own passphrase
+    let [everyday, master] = &sheet_ciphertexts(&temp)?[..] else {
+        panic!("Expected two tiers");
+    };
+    assert_eq!(
+        decrypt_sheet(everyday, PASSPHRASE, None)?,
+        b"recovery email: hunter2"
+    );
+    assert_eq!(
+        decrypt_sheet(master, "grumpy-walrus-marmalade-17", None)?,
+        b"master password: correct horse battery staple"
+    );
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_break_glass_invalid_mnemonic() -> Result<(), Box<dyn std::error::Error>> {
+    let (temp, mut cmd) = sheet_command(&[], b"recovery email: hunter2")?;
+    let break_glass = temp.child("break-glass.txt");
+    break_glass.write_str(
+        "legal winner thank year wave sausage worth useful legal winner thank thank\n",
+    )?;
+
+    // Refused before asking for any passphrase, which would fail here
+    cmd.arg("--break-glass")
+        .arg(break_glass.path())
+        .env_remove("PAPERAGE_PASSPHRASE")
+        .env_remove("PAPERAGE_BREAK_GLASS_PASSPHRASE")
+        .env("SSH_ASKPASS", temp.child("missing-askpass").path())
+        .env("SSH_ASKPASS_REQUIRE", "force");
+    cmd.assert()
+        .failure()
+        .code(exitcode::DATAERR)
+        .stderr(predicate::str::contains(
+            "Refusing to print a mnemonic with an invalid checksum",
+        ));
+
+    temp.child("output.pdf").assert(predicate::path::missing());
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_break_glass_same_passphrase() -> Result<(), Box<dyn std::error::Error>> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp = assert_fs::TempDir::new().unwrap();
+    let everyday = temp.child("everyday.txt");
+    everyday.write_str("recovery email: hunter2")?;
+    let break_glass = temp.child("break-glass.txt");
+    break_glass.write_str("master password: correct horse battery staple")?;
+    let passphrase = temp.child("passphrase.txt");
+    passphrase.write_str(PASSPHRASE)?;
+    std::fs::set_permissions(passphrase.path(), std::fs::Permissions::from_mode(0o600))?;
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("--output")
+        .arg(temp.child("output.pdf").path())
+        .arg("--break-glass")
+        .arg(break_glass.path())
+        .arg("--break-glass-passphrase-file")
+        .arg(passphrase.path())
+        .arg(everyday.path())
+        .env("PAPERAGE_PASSPHRASE", PASSPHRASE);
+    cmd.assert()
+        .failure()
+        .code(exitcode::DATAERR)
+        .stderr(predicate::str::contains("needs a different passphrase"));
+
+    Ok(())
+}
+
+#[test]
+fn test_break_glass_not_found() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let everyday = temp.child("everyday.txt");
+    everyday.write_str("recovery email: hunter2")?;
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("--output")
+        .arg(temp.child("output.pdf").path())
+        .arg("--break-glass")
+        .arg(temp.child("missing.txt").path())
+        .arg(everyday.path())
+        .env("PAPERAGE_PASSPHRASE", PASSPHRASE)
+        .env(
+            "PAPERAGE_BREAK_GLASS_PASSPHRASE",
+            "grumpy-walrus-marmalade-17",
+        );
+    cmd.assert().failure().code(exitcode::NOINPUT);
+
+    Ok(())
+}
+
+#[test]
+fn test_decrypt_nfd_passphrase() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    // Encrypted with the NFC form of the passphrase, typed here as NFD
+    cmd.arg("decrypt").arg(data_path("unicode.age")).env(
+        "PAPERAGE_PASSPHRASE",
+        "cre\u{300}me-bru\u{302}le\u{301}e-otter-42",
+    );
+    cmd.assert().success().stdout("Hello from the sheet\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_passphrase_trailing_newlines() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("decrypt")
+        .arg(data_path("passphrase.age"))
+        .env("PAPERAGE_PASSPHRASE", format!("{PASSPHRASE}\r\n\n"));
+    cmd.assert().success().stdout("Hello from the sheet\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_passphrase_typing_warnings() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let input = temp.child("sample.txt");
+    input.write_str("Hello")?;
+    let output = temp.child("output.pdf");
+    let mut cmd = Command::cargo_bin("paper-age")?;
+
+    cmd.arg("--output")
+        .arg(output.path())
+        .arg("--work-factor")
+        .arg("10")
+        .arg("-v")
+        .arg(input.path())
+        .env("PAPERAGE_PASSPHRASE", "gr\u{fc}ne-otter^tangerine-42");
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains("non-ASCII characters"))
+        .stderr(predicate::str::contains("dead keys"));
+
+    output.assert(predicate::path::is_file());
+
+    Ok(())
+}